@@ -0,0 +1,134 @@
+// Compares MioWrapper and UringDevice on a fresh TUN interface, in packets
+// per second. Needs CAP_NET_ADMIN:
+//
+//     cargo bench --features uring
+
+extern crate lsoup;
+extern crate mio;
+
+use std::io;
+use std::net::UdpSocket;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use mio::{Events, Poll, PollOpt, Ready, Token};
+
+use lsoup::driver::{Config, Device, Iface, MioWrapper, UringDevice};
+use lsoup::packet::checksum;
+
+const PACKETS: usize = 100_000;
+const PAYLOAD: usize = 64;
+const DEPTH: usize = 64;
+
+fn iface() -> Iface {
+    let mut iface = Iface::builder().name("bench%d").build().unwrap();
+    iface.up(&Config { default_rule: false, ..Config::default() }).unwrap();
+    iface
+}
+
+// UDP from 172.32.0.2 to 172.32.0.1, delivered to the discard port.
+fn udp_packet() -> Vec<u8> {
+    let len = 20 + 8 + PAYLOAD;
+    let mut packet = vec![0u8; len];
+    packet[0] = 0x45;
+    packet[2..4].clone_from_slice(&(len as u16).to_be_bytes());
+    packet[8] = 64;
+    packet[9] = 17;
+    packet[12..16].clone_from_slice(&[172, 32, 0, 2]);
+    packet[16..20].clone_from_slice(&[172, 32, 0, 1]);
+    let sum = checksum::checksum(&packet[..20]);
+    packet[10..12].clone_from_slice(&sum.to_be_bytes());
+    packet[20..22].clone_from_slice(&9999u16.to_be_bytes());
+    packet[22..24].clone_from_slice(&9u16.to_be_bytes());
+    packet[24..26].clone_from_slice(&((8 + PAYLOAD) as u16).to_be_bytes());
+    packet
+}
+
+fn report(name: &str, what: &str, packets: usize, elapsed: Duration) {
+    let secs = elapsed.as_secs_f64();
+    println!("{:<12} {:<5} {:>8} packets in {:>8.3}s, {:>10.0} pps", name, what, packets, secs, packets as f64 / secs);
+}
+
+// A full device is waited for like `TxQueue` users do, until it signals
+// that it is writable again.
+fn bench_write<D: Device>(name: &str, device: &mut D, poll: &Poll) {
+    poll.reregister(device, Token(0), Ready::writable(), PollOpt::level()).unwrap();
+
+    let packet = udp_packet();
+    let mut events = Events::with_capacity(16);
+    let start = Instant::now();
+    let mut sent = 0;
+    while sent < PACKETS {
+        match device.write_packet(&packet) {
+            Ok(_) => sent += 1,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                if poll.poll(&mut events, Some(Duration::from_secs(1))).unwrap() == 0 {
+                    panic!("{}: not writable again after a second", name);
+                }
+            },
+            Err(e) => panic!("{}: write failed: {}", name, e),
+        }
+    }
+    report(name, "write", sent, start.elapsed());
+}
+
+// Reads and drops whatever is waiting, such as the ICMP port unreachable
+// replies to the packets written, so that `bench_read` does not count them.
+fn drain<D: Device>(device: &mut D, poll: &Poll) {
+    poll.reregister(device, Token(0), Ready::readable(), PollOpt::level()).unwrap();
+
+    let mut buf = vec![0u8; device.get_buffer_size()];
+    let mut events = Events::with_capacity(16);
+    while poll.poll(&mut events, Some(Duration::from_millis(200))).unwrap() > 0 {
+        while device.read_packet(&mut buf).is_ok() {}
+    }
+}
+
+// Packets sent by a UDP socket are routed into the interface and read back,
+// until none arrived for a while.
+fn bench_read<D: Device>(name: &str, device: &mut D, poll: &Poll) {
+    poll.reregister(device, Token(0), Ready::readable(), PollOpt::level()).unwrap();
+
+    let sender = thread::spawn(|| {
+        let socket = UdpSocket::bind("172.32.0.1:0").unwrap();
+        let payload = [0u8; PAYLOAD];
+        for _ in 0..PACKETS {
+            // The interface queue overflows now and then.
+            let _ = socket.send_to(&payload, "172.32.0.2:9");
+        }
+    });
+
+    let mut buf = vec![0u8; device.get_buffer_size()];
+    let mut events = Events::with_capacity(16);
+    let mut received = 0;
+    let start = Instant::now();
+    let mut last = start;
+    while poll.poll(&mut events, Some(Duration::from_millis(200))).unwrap() > 0 {
+        loop {
+            match device.read_packet(&mut buf) {
+                Ok(_) => received += 1,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => panic!("{}: read failed: {}", name, e),
+            }
+        }
+        last = Instant::now();
+    }
+    sender.join().unwrap();
+    report(name, "read", received, last - start);
+}
+
+// One `Poll` per device for all phases, a `UringDevice` cannot move to
+// another.
+fn bench<D: Device>(name: &str, device: &mut D) {
+    let poll = Poll::new().unwrap();
+    poll.register(device, Token(0), Ready::readable(), PollOpt::level()).unwrap();
+    bench_write(name, device, &poll);
+    drain(device, &poll);
+    bench_read(name, device, &poll);
+    poll.deregister(device).unwrap();
+}
+
+fn main() {
+    bench("MioWrapper", &mut MioWrapper::new(iface()).unwrap());
+    bench("UringDevice", &mut UringDevice::new(iface(), DEPTH).unwrap());
+}
@@ -0,0 +1,338 @@
+/*
+ * Reference:
+ *  https://www.kernel.org/doc/Documentation/networking/packet_mmap.txt
+ *  https://github.com/torvalds/linux/blob/master/include/uapi/linux/if_packet.h
+ *
+ * Date: Oct 18 CST 2026
+ */
+
+use std::{io, mem, ptr};
+use std::sync::atomic::{fence, Ordering};
+
+use libc;
+use libc::{c_int, c_uint, c_ushort, c_void};
+use mio::{Evented, Ready, Poll, PollOpt, Token};
+use mio::unix::EventedFd;
+
+use driver::{cvt, Device, Error, IoctlFlags, Mode, Result};
+use driver::tun::{ifr_name, ioctl, IfrIfru, Ifreq};
+use driver::{ETHERNET_OVERHEAD, MAX_PACKET_SIZE};
+
+const ETH_P_ALL: u16 = 0x0003;
+
+const SOL_PACKET: c_int = 263;
+const PACKET_ADD_MEMBERSHIP: c_int = 1;
+const PACKET_DROP_MEMBERSHIP: c_int = 2;
+const PACKET_RX_RING: c_int = 5;
+const PACKET_VERSION: c_int = 10;
+const PACKET_IGNORE_OUTGOING: c_int = 23;
+
+const PACKET_MR_PROMISC: c_ushort = 1;
+
+const TPACKET_V2: c_int = 1;
+const TPACKET_ALIGNMENT: usize = 16;
+const TP_STATUS_KERNEL: u32 = 0;
+const TP_STATUS_USER: u32 = 1;
+
+#[repr(C)]
+struct PacketMreq {
+    mr_ifindex: c_int,
+    mr_type: c_ushort,
+    mr_alen: c_ushort,
+    mr_address: [u8; 8],
+}
+
+#[repr(C)]
+struct TpacketReq {
+    tp_block_size: c_uint,
+    tp_block_nr: c_uint,
+    tp_frame_size: c_uint,
+    tp_frame_nr: c_uint,
+}
+
+/*
+    Ring frame of TPACKET_V2:
+        +--------------+-------------+-------------------+----------------+
+        | tpacket2_hdr | sockaddr_ll | padding up to mac | packet         |
+        +--------------+-------------+-------------------+----------------+
+        0                                               tp_mac
+*/
+#[repr(C)]
+struct Tpacket2Hdr {
+    tp_status: u32,
+    tp_len: u32,
+    tp_snaplen: u32,
+    tp_mac: u16,
+    tp_net: u16,
+    tp_sec: u32,
+    tp_nsec: u32,
+    tp_vlan_tci: u16,
+    tp_vlan_tpid: u16,
+    tp_padding: [u8; 4],
+}
+
+fn tpacket_align(len: usize) -> usize {
+    (len + TPACKET_ALIGNMENT - 1) & !(TPACKET_ALIGNMENT - 1)
+}
+
+fn setsockopt<T>(fd: c_int, name: c_int, value: &T) -> io::Result<()> {
+    cvt(unsafe {
+        libc::setsockopt(fd, SOL_PACKET, name, value as *const T as *const c_void,
+                         mem::size_of::<T>() as libc::socklen_t)
+    })?;
+    Ok(())
+}
+
+// PACKET_MMAP receive ring: the kernel fills frames and hands them over with
+// TP_STATUS_USER, we give them back with TP_STATUS_KERNEL in the same order.
+struct Ring {
+    base: *mut u8,
+    size: usize,
+    frame_size: usize,
+    frame_nr: usize,
+    next: usize,
+}
+
+// The mapping is only ever touched through `&mut PacketSocket`.
+unsafe impl Send for Ring {}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.base as *mut c_void, self.size) };
+    }
+}
+
+impl Ring {
+
+    fn new(fd: c_int, frame_size: usize, frames: usize) -> io::Result<Self> {
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let block_size = frame_size.max(page_size).next_power_of_two();
+        let per_block = block_size / frame_size;
+        let block_nr = frames.div_ceil(per_block).max(1);
+
+        let req = TpacketReq {
+            tp_block_size: block_size as c_uint,
+            tp_block_nr: block_nr as c_uint,
+            tp_frame_size: frame_size as c_uint,
+            tp_frame_nr: (block_nr * per_block) as c_uint,
+        };
+        setsockopt(fd, PACKET_VERSION, &TPACKET_V2)?;
+        setsockopt(fd, PACKET_RX_RING, &req)?;
+
+        let size = block_size * block_nr;
+        let base = unsafe {
+            libc::mmap(ptr::null_mut(), size, libc::PROT_READ | libc::PROT_WRITE,
+                       libc::MAP_SHARED, fd, 0)
+        };
+        if base == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Ring {
+            base: base as *mut u8,
+            size,
+            frame_size,
+            frame_nr: block_nr * per_block,
+            next: 0,
+        })
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let frame = unsafe { self.base.add(self.next * self.frame_size) };
+        let hdr = frame as *mut Tpacket2Hdr;
+
+        if unsafe { ptr::read_volatile(&(*hdr).tp_status) } & TP_STATUS_USER == 0 {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        fence(Ordering::Acquire);
+
+        let (mac, len, snaplen) = unsafe {
+            ((*hdr).tp_mac as usize, (*hdr).tp_len as usize, (*hdr).tp_snaplen as usize)
+        };
+        let copied = snaplen.min(buf.len());
+        unsafe { ptr::copy_nonoverlapping(frame.add(mac), buf.as_mut_ptr(), copied) };
+
+        fence(Ordering::Release);
+        unsafe { ptr::write_volatile(&mut (*hdr).tp_status, TP_STATUS_KERNEL) };
+        self.next = (self.next + 1) % self.frame_nr;
+
+        if copied < len {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("packet of {} bytes truncated to {}", len, copied)));
+        }
+        Ok(copied)
+    }
+}
+
+// An AF_PACKET socket bound to an existing interface, such as a veth or a
+// bridge. Packets we send are not read back. Ethernet and loopback links
+// give `Mode::Tap` frames, links without a hardware header, such as TUN
+// devices, `Mode::Tun` packets; other link types are refused.
+pub struct PacketSocket {
+    fd: c_int,
+    name: String,
+    index: c_int,
+    mode: Mode,
+    buffer_size: usize,
+    ring: Option<Ring>,
+}
+
+impl Drop for PacketSocket {
+    fn drop(&mut self) {
+        self.ring = None;
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+impl PacketSocket {
+
+    pub fn bind(name: &str) -> Result<Self> {
+        PacketSocket::open(name, None)
+    }
+
+    // Receives through a PACKET_MMAP ring of at least `frames` frames, which
+    // saves a copy and a system call per packet.
+    pub fn with_ring(name: &str, frames: usize) -> Result<Self> {
+        PacketSocket::open(name, Some(frames))
+    }
+
+    // The socket is created with protocol 0, which receives nothing until
+    // `bind` sets ETH_P_ALL together with the interface: otherwise frames of
+    // every interface would be queued in the meantime.
+    fn open(name: &str, frames: Option<usize>) -> Result<Self> {
+        let mut ifreq = Ifreq {
+            ifr_name: ifr_name(name)?,
+            ifr_ifru: IfrIfru { ifru_pad: [0; 24] },
+        };
+
+        let fd = cvt(unsafe {
+            libc::socket(libc::AF_PACKET, libc::SOCK_RAW | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC, 0)
+        }).map_err(Error::open)?;
+        let mut socket = PacketSocket {
+            fd,
+            name: name.to_string(),
+            index: 0,
+            mode: Mode::Tap,
+            buffer_size: 0,
+            ring: None,
+        };
+
+        socket.index = match unsafe { libc::if_nametoindex(ifreq.ifr_name.as_ptr()) } {
+            0 => return Err(Error::Io(io::Error::last_os_error())),
+            index => index as c_int,
+        };
+
+        ioctl(fd, IoctlFlags::SIOCGIFHWADDR, &mut ifreq)?;
+        socket.mode = match unsafe { ifreq.ifr_ifru.ifru_hwaddr.sa_family } {
+            libc::ARPHRD_ETHER | libc::ARPHRD_LOOPBACK => Mode::Tap,
+            libc::ARPHRD_NONE => Mode::Tun,
+            linktype => return Err(Error::Io(io::Error::new(io::ErrorKind::Unsupported,
+                                                            format!("{} has unsupported link type {}", name, linktype)))),
+        };
+        // Like `Tun::get_buffer_size`, for the largest packet rather than the
+        // MTU at open: GRO coalesces beyond it, and it may be raised later.
+        socket.buffer_size = match socket.mode {
+            Mode::Tun => MAX_PACKET_SIZE,
+            Mode::Tap => MAX_PACKET_SIZE + ETHERNET_OVERHEAD,
+        };
+
+        setsockopt(fd, PACKET_IGNORE_OUTGOING, &(1 as c_int))?;
+        if let Some(frames) = frames {
+            let header = tpacket_align(mem::size_of::<Tpacket2Hdr>()) + mem::size_of::<libc::sockaddr_ll>();
+            let frame_size = (tpacket_align(header) + TPACKET_ALIGNMENT + socket.buffer_size).next_power_of_two();
+            socket.ring = Some(Ring::new(fd, frame_size, frames)?);
+        }
+
+        let mut addr: libc::sockaddr_ll = unsafe { mem::zeroed() };
+        addr.sll_family = libc::AF_PACKET as c_ushort;
+        addr.sll_protocol = ETH_P_ALL.to_be();
+        addr.sll_ifindex = socket.index;
+        cvt(unsafe {
+            libc::bind(fd, &addr as *const libc::sockaddr_ll as *const libc::sockaddr,
+                       mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t)
+        })?;
+
+        Ok(socket)
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn has_ring(&self) -> bool {
+        self.ring.is_some()
+    }
+
+    // Also receive frames not addressed to the interface. The kernel drops
+    // the membership when the socket is closed.
+    pub fn set_promiscuous(&self, promiscuous: bool) -> Result<()> {
+        let mreq = PacketMreq {
+            mr_ifindex: self.index,
+            mr_type: PACKET_MR_PROMISC,
+            mr_alen: 0,
+            mr_address: [0; 8],
+        };
+        let name = if promiscuous { PACKET_ADD_MEMBERSHIP } else { PACKET_DROP_MEMBERSHIP };
+        setsockopt(self.fd, name, &mreq)?;
+        Ok(())
+    }
+}
+
+impl Evented for PacketSocket {
+
+    fn register(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt)
+        -> io::Result<()> {
+        EventedFd(&self.fd).register(poll, token, interest, opts)
+    }
+
+    fn reregister(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt)
+        -> io::Result<()> {
+        EventedFd(&self.fd).reregister(poll, token, interest, opts)
+    }
+
+    fn deregister(&self, poll: &Poll) -> io::Result<()> {
+        EventedFd(&self.fd).deregister(poll)
+    }
+}
+
+impl Device for PacketSocket {
+    fn get_mode(&self) -> Mode {
+        self.mode
+    }
+
+    fn get_mtu(&self) -> io::Result<usize> {
+        let mut ifreq = Ifreq {
+            ifr_name: ifr_name(&self.name)?,
+            ifr_ifru: IfrIfru { ifru_mtu: 0 },
+        };
+        ioctl(self.fd, IoctlFlags::SIOCGIFMTU, &mut ifreq)?;
+        Ok(unsafe { ifreq.ifr_ifru.ifru_mtu } as usize)
+    }
+
+    fn get_buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    fn read_packet(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(ref mut ring) = self.ring {
+            return ring.read(buf);
+        }
+
+        // MSG_TRUNC returns the real length, so that truncation is noticed.
+        let len = cvt(unsafe {
+            libc::recv(self.fd, buf.as_mut_ptr() as *mut c_void, buf.len(), libc::MSG_TRUNC)
+        })? as usize;
+        if len > buf.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("packet of {} bytes truncated to {}", len, buf.len())));
+        }
+        Ok(len)
+    }
+
+    fn write_packet(&mut self, packet: &[u8]) -> io::Result<usize> {
+        let len = cvt(unsafe {
+            libc::send(self.fd, packet.as_ptr() as *const c_void, packet.len(), 0)
+        })?;
+        Ok(len as usize)
+    }
+}
@@ -0,0 +1,99 @@
+use std::io;
+use std::io::{Read, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::io::unix::AsyncFd;
+
+use driver::{Iface, Result};
+
+// `Iface` driven by the tokio reactor. Every read returns exactly one packet
+// and every write sends one, headers included, just like the blocking
+// `Read` and `Write` of `Iface`; size read buffers with `get_buffer_size`.
+#[derive(Debug)]
+pub struct AsyncTun {
+    inner: AsyncFd<Iface>,
+    buffer_size: usize,
+}
+
+impl AsyncTun {
+
+    // Must be called from within a tokio runtime.
+    pub fn new(iface: Iface) -> Result<Self> {
+        iface.set_nonblocking()?;
+        let buffer_size = iface.get_buffer_size();
+        // The fd is owned by `iface`, which stays inside the AsyncFd until
+        // `into_inner` deregisters it.
+        let inner = unsafe { AsyncFd::register(iface) }.map_err(io::Error::from)?;
+        Ok(AsyncTun {
+            inner,
+            buffer_size,
+        })
+    }
+
+    pub fn get_buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    pub fn get_ref(&self) -> &Iface {
+        self.inner.get_ref()
+    }
+
+    pub fn get_mut(&mut self) -> &mut Iface {
+        self.inner.get_mut()
+    }
+
+    pub fn into_inner(self) -> Iface {
+        self.inner.into_inner()
+    }
+}
+
+impl AsyncRead for AsyncTun {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut ReadBuf)
+        -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            let mut guard = match this.inner.poll_read_ready_mut(cx) {
+                Poll::Ready(guard) => guard?,
+                Poll::Pending => return Poll::Pending,
+            };
+
+            match guard.try_io(|inner| inner.get_mut().read(buf.initialize_unfilled())) {
+                Ok(Ok(len)) => {
+                    buf.advance(len);
+                    return Poll::Ready(Ok(()));
+                },
+                Ok(Err(e)) => return Poll::Ready(Err(e)),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+impl AsyncWrite for AsyncTun {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8])
+        -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            let mut guard = match this.inner.poll_write_ready_mut(cx) {
+                Poll::Ready(guard) => guard?,
+                Poll::Pending => return Poll::Pending,
+            };
+
+            match guard.try_io(|inner| inner.get_mut().write(buf)) {
+                Ok(result) => return Poll::Ready(result),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    // Packets go out with every write, there is nothing to flush or shut down.
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,633 @@
+/*
+ * Reference:
+ *  https://www.kernel.org/doc/Documentation/networking/filter.txt
+ *  https://www.tcpdump.org/manpages/pcap-filter.7.html
+ *
+ * Date: Oct 18 CST 2026
+ */
+
+use std::io;
+use std::net::IpAddr;
+
+use driver::{Error, Mode, Result};
+use packet::{ETHERTYPE_ARP, ETHERTYPE_IPV4, ETHERTYPE_IPV6};
+
+const BPF_LD: u16 = 0x00;
+const BPF_LDX: u16 = 0x01;
+const BPF_ALU: u16 = 0x04;
+const BPF_JMP: u16 = 0x05;
+const BPF_RET: u16 = 0x06;
+
+const BPF_W: u16 = 0x00;
+const BPF_H: u16 = 0x08;
+const BPF_B: u16 = 0x10;
+
+const BPF_ABS: u16 = 0x20;
+const BPF_IND: u16 = 0x40;
+const BPF_MSH: u16 = 0xa0;
+
+const BPF_AND: u16 = 0x50;
+const BPF_JEQ: u16 = 0x10;
+const BPF_JSET: u16 = 0x40;
+
+const BPF_MAXINSNS: usize = 4096;
+
+const IPPROTO_ICMP: u32 = 1;
+const IPPROTO_TCP: u32 = 6;
+const IPPROTO_UDP: u32 = 17;
+const IPPROTO_ICMPV6: u32 = 58;
+
+// struct sock_filter
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SockFilter {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+// struct sock_fprog
+#[repr(C)]
+pub(super) struct SockFprog {
+    pub(super) len: u16,
+    pub(super) filter: *const SockFilter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Size {
+    Word,
+    Half,
+    Byte,
+}
+
+impl Size {
+    fn bits(self) -> u16 {
+        match self {
+            Size::Word => BPF_W,
+            Size::Half => BPF_H,
+            Size::Byte => BPF_B,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Load {
+    Abs(Size, u32),
+    // Relative to the end of the IPv4 header starting at `header`.
+    Ind(Size, u32, u32),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Test {
+    Equal(u32),
+    AnySet(u32),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Atom {
+    load: Load,
+    mask: Option<u32>,
+    test: Test,
+}
+
+#[derive(Debug)]
+enum Expr {
+    Atom(Atom),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+fn atom(size: Size, offset: u32, value: u32) -> Expr {
+    Expr::Atom(Atom { load: Load::Abs(size, offset), mask: None, test: Test::Equal(value) })
+}
+
+fn and(a: Expr, b: Expr) -> Expr {
+    Expr::And(Box::new(a), Box::new(b))
+}
+
+fn or(a: Expr, b: Expr) -> Expr {
+    Expr::Or(Box::new(a), Box::new(b))
+}
+
+fn not(a: Expr) -> Expr {
+    Expr::Not(Box::new(a))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Src,
+    Dst,
+    Any,
+}
+
+fn invalid(msg: String) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidInput, msg))
+}
+
+// Builds the tests of the primitives on packets as the interface delivers
+// them: IP packets for TUN, Ethernet frames for TAP. VLAN tags and IPv6
+// extension headers are not looked through.
+struct Primitives {
+    mode: Mode,
+    // Offset of the IP header.
+    l3: u32,
+}
+
+impl Primitives {
+
+    fn ipv4(&self) -> Expr {
+        match self.mode {
+            Mode::Tun => Expr::Atom(Atom { load: Load::Abs(Size::Byte, 0), mask: Some(0xf0), test: Test::Equal(0x40) }),
+            Mode::Tap => atom(Size::Half, 12, ETHERTYPE_IPV4 as u32),
+        }
+    }
+
+    fn ipv6(&self) -> Expr {
+        match self.mode {
+            Mode::Tun => Expr::Atom(Atom { load: Load::Abs(Size::Byte, 0), mask: Some(0xf0), test: Test::Equal(0x60) }),
+            Mode::Tap => atom(Size::Half, 12, ETHERTYPE_IPV6 as u32),
+        }
+    }
+
+    fn arp(&self) -> Result<Expr> {
+        match self.mode {
+            Mode::Tun => Err(invalid("arp needs a TAP interface".to_string())),
+            Mode::Tap => Ok(atom(Size::Half, 12, ETHERTYPE_ARP as u32)),
+        }
+    }
+
+    fn proto4(&self, proto: u32) -> Expr {
+        and(self.ipv4(), atom(Size::Byte, self.l3 + 9, proto))
+    }
+
+    fn proto6(&self, proto: u32) -> Expr {
+        and(self.ipv6(), atom(Size::Byte, self.l3 + 6, proto))
+    }
+
+    fn proto(&self, proto: u32) -> Expr {
+        or(self.proto4(proto), self.proto6(proto))
+    }
+
+    fn select(direction: Direction, src: Expr, dst: Expr) -> Expr {
+        match direction {
+            Direction::Src => src,
+            Direction::Dst => dst,
+            Direction::Any => or(src, dst),
+        }
+    }
+
+    // Compares 32 bits at a time, up to four words for an IPv6 address.
+    // `prefix_len` must not be zero.
+    fn address(offset: u32, octets: &[u8], prefix_len: u32) -> Expr {
+        let mut expr: Option<Expr> = None;
+        for (i, word) in octets.chunks(4).enumerate() {
+            let bits = prefix_len.saturating_sub(32 * i as u32).min(32);
+            if bits == 0 {
+                break;
+            }
+            let mask = if bits == 32 { u32::MAX } else { !(u32::MAX >> bits) };
+            let value = u32::from_be_bytes([word[0], word[1], word[2], word[3]]) & mask;
+            let test = Expr::Atom(Atom {
+                load: Load::Abs(Size::Word, offset + 4 * i as u32),
+                mask: if bits == 32 { None } else { Some(mask) },
+                test: Test::Equal(value),
+            });
+            expr = Some(match expr {
+                Some(expr) => and(expr, test),
+                None => test,
+            });
+        }
+        expr.unwrap()
+    }
+
+    fn net(&self, direction: Direction, address: IpAddr, prefix_len: u32) -> Expr {
+        let (family, octets, src, dst) = match address {
+            IpAddr::V4(address) => (self.ipv4(), address.octets().to_vec(), self.l3 + 12, self.l3 + 16),
+            IpAddr::V6(address) => (self.ipv6(), address.octets().to_vec(), self.l3 + 8, self.l3 + 24),
+        };
+        if prefix_len == 0 {
+            return family;
+        }
+        and(family, Self::select(direction,
+                                 Self::address(src, &octets, prefix_len),
+                                 Self::address(dst, &octets, prefix_len)))
+    }
+
+    // TCP or UDP, and for IPv4 only the first fragment carries the ports.
+    fn port(&self, direction: Direction, port: u16) -> Expr {
+        let l3 = self.l3;
+        let port = port as u32;
+        let ind = |offset| Expr::Atom(Atom { load: Load::Ind(Size::Half, l3, offset), mask: None, test: Test::Equal(port) });
+
+        let v4 = and(
+            and(self.ipv4(), or(atom(Size::Byte, l3 + 9, IPPROTO_TCP), atom(Size::Byte, l3 + 9, IPPROTO_UDP))),
+            and(not(Expr::Atom(Atom { load: Load::Abs(Size::Half, l3 + 6), mask: None, test: Test::AnySet(0x1fff) })),
+                Self::select(direction, ind(0), ind(2))));
+        let v6 = and(
+            and(self.ipv6(), or(atom(Size::Byte, l3 + 6, IPPROTO_TCP), atom(Size::Byte, l3 + 6, IPPROTO_UDP))),
+            Self::select(direction, atom(Size::Half, l3 + 40, port), atom(Size::Half, l3 + 42, port)));
+        or(v4, v6)
+    }
+}
+
+/*
+    Filter expressions, a small subset of pcap-filter(7):
+        expr      := term { ("or" | "||") term }
+        term      := factor { ("and" | "&&") factor }
+        factor    := ("not" | "!") factor | "(" expr ")" | primitive
+        primitive := "ip" | "ip6" | "arp" | "tcp" | "udp" | "icmp" | "icmp6"
+                   | [ "src" | "dst" ] "host" ADDRESS
+                   | [ "src" | "dst" ] "net" ADDRESS "/" PREFIX_LEN
+                   | [ "src" | "dst" ] "port" PORT
+                   | ( "tcp" | "udp" ) [ "src" | "dst" ] "port" PORT
+*/
+struct Parser<'a> {
+    tokens: Vec<&'a str>,
+    next: usize,
+    primitives: Primitives,
+}
+
+impl<'a> Parser<'a> {
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.next).cloned()
+    }
+
+    fn take(&mut self) -> Result<&'a str> {
+        let token = self.peek().ok_or_else(|| invalid("unexpected end of filter".to_string()))?;
+        self.next += 1;
+        Ok(token)
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        let mut expr = self.term()?;
+        while let Some("or") | Some("||") = self.peek() {
+            self.next += 1;
+            expr = or(expr, self.term()?);
+        }
+        Ok(expr)
+    }
+
+    fn term(&mut self) -> Result<Expr> {
+        let mut expr = self.factor()?;
+        while let Some("and") | Some("&&") = self.peek() {
+            self.next += 1;
+            expr = and(expr, self.factor()?);
+        }
+        Ok(expr)
+    }
+
+    fn factor(&mut self) -> Result<Expr> {
+        match self.take()? {
+            "not" | "!" => Ok(not(self.factor()?)),
+            "(" => {
+                let expr = self.expr()?;
+                match self.take()? {
+                    ")" => Ok(expr),
+                    token => Err(invalid(format!("expected ')' but found '{}'", token))),
+                }
+            },
+            token => self.primitive(token),
+        }
+    }
+
+    fn primitive(&mut self, token: &str) -> Result<Expr> {
+        let p = &self.primitives;
+        match token {
+            "ip" => return Ok(p.ipv4()),
+            "ip6" => return Ok(p.ipv6()),
+            "arp" => return p.arp(),
+            "tcp" | "udp" => {
+                let proto = p.proto(if token == "tcp" { IPPROTO_TCP } else { IPPROTO_UDP });
+                // Qualifies a following port, as in "tcp dst port 80".
+                return match self.peek() {
+                    Some("port") | Some("src") | Some("dst") => {
+                        let token = self.take()?;
+                        Ok(and(proto, self.primitive(token)?))
+                    },
+                    _ => Ok(proto),
+                };
+            },
+            "icmp" => return Ok(p.proto4(IPPROTO_ICMP)),
+            "icmp6" => return Ok(p.proto6(IPPROTO_ICMPV6)),
+            _ => (),
+        }
+
+        let (direction, token) = match token {
+            "src" => (Direction::Src, self.take()?),
+            "dst" => (Direction::Dst, self.take()?),
+            token => (Direction::Any, token),
+        };
+        let value = self.take()?;
+        match token {
+            "host" => {
+                let address = value.parse::<IpAddr>()
+                    .map_err(|_| invalid(format!("invalid host '{}'", value)))?;
+                let prefix_len = if address.is_ipv4() { 32 } else { 128 };
+                Ok(self.primitives.net(direction, address, prefix_len))
+            },
+            "net" => {
+                let (address, prefix_len) = value.split_once('/')
+                    .and_then(|(address, prefix_len)| Some((address.parse::<IpAddr>().ok()?, prefix_len.parse::<u32>().ok()?)))
+                    .filter(|&(address, prefix_len)| prefix_len <= if address.is_ipv4() { 32 } else { 128 })
+                    .ok_or_else(|| invalid(format!("invalid net '{}'", value)))?;
+                Ok(self.primitives.net(direction, address, prefix_len))
+            },
+            "port" => {
+                let port = value.parse::<u16>()
+                    .map_err(|_| invalid(format!("invalid port '{}'", value)))?;
+                Ok(self.primitives.port(direction, port))
+            },
+            token => Err(invalid(format!("unknown filter primitive '{}'", token))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Target {
+    Next,
+    Label(usize),
+}
+
+struct Insn {
+    code: u16,
+    jt: Target,
+    jf: Target,
+    k: u32,
+}
+
+// Short-circuit code generation: every expression jumps to `on_true` or
+// `on_false`, which are resolved to forward offsets once all code is out.
+struct Codegen {
+    insns: Vec<Insn>,
+    labels: Vec<usize>,
+}
+
+impl Codegen {
+
+    fn label(&mut self) -> usize {
+        self.labels.push(usize::MAX);
+        self.labels.len() - 1
+    }
+
+    fn place(&mut self, label: usize) {
+        self.labels[label] = self.insns.len();
+    }
+
+    fn emit(&mut self, code: u16, k: u32) {
+        self.insns.push(Insn { code, jt: Target::Next, jf: Target::Next, k });
+    }
+
+    fn expr(&mut self, expr: &Expr, on_true: usize, on_false: usize) {
+        match *expr {
+            Expr::Atom(ref atom) => {
+                match atom.load {
+                    Load::Abs(size, offset) => self.emit(BPF_LD | size.bits() | BPF_ABS, offset),
+                    Load::Ind(size, header, offset) => {
+                        self.emit(BPF_LDX | BPF_B | BPF_MSH, header);
+                        self.emit(BPF_LD | size.bits() | BPF_IND, header + offset);
+                    },
+                }
+                if let Some(mask) = atom.mask {
+                    self.emit(BPF_ALU | BPF_AND, mask);
+                }
+                let (code, k) = match atom.test {
+                    Test::Equal(k) => (BPF_JMP | BPF_JEQ, k),
+                    Test::AnySet(k) => (BPF_JMP | BPF_JSET, k),
+                };
+                self.insns.push(Insn { code, jt: Target::Label(on_true), jf: Target::Label(on_false), k });
+            },
+            Expr::And(ref a, ref b) => {
+                let next = self.label();
+                self.expr(a, next, on_false);
+                self.place(next);
+                self.expr(b, on_true, on_false);
+            },
+            Expr::Or(ref a, ref b) => {
+                let next = self.label();
+                self.expr(a, on_true, next);
+                self.place(next);
+                self.expr(b, on_true, on_false);
+            },
+            Expr::Not(ref a) => self.expr(a, on_false, on_true),
+        }
+    }
+
+    fn finish(self) -> Result<Vec<SockFilter>> {
+        let labels = self.labels;
+        let offset = |at: usize, target: Target| -> Result<u8> {
+            match target {
+                Target::Next => Ok(0),
+                Target::Label(label) => {
+                    let distance = labels[label] - at - 1;
+                    if distance > u8::MAX as usize {
+                        return Err(invalid("filter is too long for its jumps".to_string()));
+                    }
+                    Ok(distance as u8)
+                },
+            }
+        };
+
+        self.insns.iter().enumerate().map(|(at, insn)| {
+            Ok(SockFilter { code: insn.code, jt: offset(at, insn.jt)?, jf: offset(at, insn.jf)?, k: insn.k })
+        }).collect()
+    }
+}
+
+// A classic BPF program compiled from a filter expression, such as
+// "udp and dst port 53" or "not (tcp port 22)". Matching packets are
+// accepted whole, all others dropped. Programs for `Mode::Tun` match raw IP
+// packets, e.g. on a socket, but the kernel only runs TUNATTACHFILTER
+// programs on TAP interfaces, so `Iface::attach_filter` refuses them.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    mode: Mode,
+    program: Vec<SockFilter>,
+}
+
+impl Filter {
+
+    pub fn compile(expression: &str, mode: Mode) -> Result<Self> {
+        let spaced = expression.replace('(', " ( ").replace(')', " ) ").replace('!', " ! ");
+        let mut parser = Parser {
+            tokens: spaced.split_whitespace().collect(),
+            next: 0,
+            primitives: Primitives {
+                mode,
+                l3: match mode {
+                    Mode::Tun => 0,
+                    Mode::Tap => 14,
+                },
+            },
+        };
+        let expr = parser.expr()?;
+        if let Some(token) = parser.peek() {
+            return Err(invalid(format!("unexpected '{}' in filter", token)));
+        }
+
+        let mut codegen = Codegen { insns: Vec::new(), labels: Vec::new() };
+        let (accept, reject) = (codegen.label(), codegen.label());
+        codegen.expr(&expr, accept, reject);
+        codegen.place(accept);
+        codegen.emit(BPF_RET, u32::MAX);
+        codegen.place(reject);
+        codegen.emit(BPF_RET, 0);
+
+        let program = codegen.finish()?;
+        if program.len() > BPF_MAXINSNS {
+            return Err(invalid(format!("filter of {} instructions exceeds {}", program.len(), BPF_MAXINSNS)));
+        }
+        Ok(Filter { mode, program })
+    }
+
+    pub fn get_mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn instructions(&self) -> &[SockFilter] {
+        &self.program
+    }
+
+    pub(super) fn as_fprog(&self) -> SockFprog {
+        SockFprog {
+            len: self.program.len() as u16,
+            filter: self.program.as_ptr(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use driver::Mode;
+    use super::*;
+
+    // Just enough of a classic BPF interpreter for the compiled programs.
+    fn run(filter: &Filter, packet: &[u8]) -> bool {
+        let load = |size: u16, offset: usize| -> Option<u32> {
+            let len = match size { BPF_W => 4, BPF_H => 2, _ => 1 };
+            let bytes = packet.get(offset..offset + len)?;
+            Some(bytes.iter().fold(0u32, |value, &b| (value << 8) | b as u32))
+        };
+
+        let program = filter.instructions();
+        let (mut a, mut x, mut pc) = (0u32, 0u32, 0usize);
+        loop {
+            let insn = program[pc];
+            pc += 1;
+            let (class, size, mode) = (insn.code & 0x07, insn.code & 0x18, insn.code & 0xe0);
+            match class {
+                BPF_LD => {
+                    let offset = if mode == BPF_IND { x + insn.k } else { insn.k } as usize;
+                    match load(size, offset) {
+                        Some(value) => a = value,
+                        None => return false,
+                    }
+                },
+                BPF_LDX => match load(BPF_B, insn.k as usize) {
+                    Some(value) => x = (value & 0xf) * 4,
+                    None => return false,
+                },
+                BPF_ALU => a &= insn.k,
+                BPF_JMP => {
+                    let taken = match insn.code & 0xf0 {
+                        BPF_JEQ => a == insn.k,
+                        BPF_JSET => a & insn.k != 0,
+                        op => panic!("unexpected jump {:#x}", op),
+                    };
+                    pc += if taken { insn.jt } else { insn.jf } as usize;
+                },
+                BPF_RET => return insn.k != 0,
+                class => panic!("unexpected class {:#x}", class),
+            }
+        }
+    }
+
+    fn ipv4(proto: u8, src: [u8; 4], dst: [u8; 4], sport: u16, dport: u16) -> Vec<u8> {
+        let mut packet = vec![0u8; 28];
+        packet[0] = 0x45;
+        packet[9] = proto;
+        packet[12..16].clone_from_slice(&src);
+        packet[16..20].clone_from_slice(&dst);
+        packet[20..22].clone_from_slice(&sport.to_be_bytes());
+        packet[22..24].clone_from_slice(&dport.to_be_bytes());
+        packet
+    }
+
+    fn ipv6(next: u8, sport: u16, dport: u16) -> Vec<u8> {
+        let mut packet = vec![0u8; 48];
+        packet[0] = 0x60;
+        packet[6] = next;
+        packet[8..24].clone_from_slice(&"2001:db8::1".parse::<::std::net::Ipv6Addr>().unwrap().octets());
+        packet[40..42].clone_from_slice(&sport.to_be_bytes());
+        packet[42..44].clone_from_slice(&dport.to_be_bytes());
+        packet
+    }
+
+    fn frame(ethertype: u16, packet: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0u8; 14];
+        frame[12..14].clone_from_slice(&ethertype.to_be_bytes());
+        frame.extend_from_slice(packet);
+        frame
+    }
+
+    #[test]
+    fn matches_protocols_and_ports() {
+        let filter = Filter::compile("udp and dst port 53", Mode::Tun).unwrap();
+        assert!(run(&filter, &ipv4(17, [10, 0, 0, 1], [10, 0, 0, 2], 4000, 53)));
+        assert!(run(&filter, &ipv6(17, 4000, 53)));
+        assert!(!run(&filter, &ipv4(17, [10, 0, 0, 1], [10, 0, 0, 2], 53, 4000)));
+        assert!(!run(&filter, &ipv4(6, [10, 0, 0, 1], [10, 0, 0, 2], 4000, 53)));
+        assert!(!run(&filter, &ipv6(6, 4000, 53)));
+    }
+
+    #[test]
+    fn skips_later_fragments() {
+        let filter = Filter::compile("port 53", Mode::Tun).unwrap();
+        let mut packet = ipv4(17, [10, 0, 0, 1], [10, 0, 0, 2], 4000, 53);
+        assert!(run(&filter, &packet));
+        packet[7] = 0x10;
+        assert!(!run(&filter, &packet));
+    }
+
+    #[test]
+    fn follows_ip_header_length() {
+        let filter = Filter::compile("tcp port 22", Mode::Tun).unwrap();
+        let mut packet = ipv4(6, [10, 0, 0, 1], [10, 0, 0, 2], 1, 1);
+        packet[0] = 0x46;
+        packet.splice(20..20, [0u8; 4].iter().cloned());
+        packet[24..26].clone_from_slice(&22u16.to_be_bytes());
+        assert!(run(&filter, &packet));
+    }
+
+    #[test]
+    fn matches_hosts_and_nets() {
+        let packet = ipv4(1, [192, 168, 1, 10], [10, 0, 0, 2], 0, 0);
+        let matches = |expression| run(&Filter::compile(expression, Mode::Tun).unwrap(), &packet);
+        assert!(matches("host 10.0.0.2"));
+        assert!(matches("src host 192.168.1.10"));
+        assert!(!matches("dst host 192.168.1.10"));
+        assert!(matches("src net 192.168.0.0/16 and icmp"));
+        assert!(!matches("net 172.16.0.0/12"));
+        assert!(matches("not (udp or tcp) && !ip6"));
+        assert!(matches("src net 0.0.0.0/0"));
+        assert!(run(&Filter::compile("src net 2001:db8::/32", Mode::Tun).unwrap(), &ipv6(6, 1, 1)));
+    }
+
+    #[test]
+    fn looks_past_ethernet_header() {
+        let filter = Filter::compile("ip and tcp port 80 or arp", Mode::Tap).unwrap();
+        assert!(run(&filter, &frame(0x0800, &ipv4(6, [10, 0, 0, 1], [10, 0, 0, 2], 80, 1))));
+        assert!(run(&filter, &frame(0x0806, &[0u8; 28])));
+        assert!(!run(&filter, &frame(0x86dd, &ipv6(6, 80, 1))));
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        for expression in &["", "tcp and", "(udp", "udp)", "port http", "net 10.0.0.0", "host 10.0.0.256", "foo"] {
+            assert!(Filter::compile(expression, Mode::Tun).is_err(), "{:?} compiled", expression);
+        }
+        assert!(Filter::compile("arp", Mode::Tun).is_err());
+    }
+}
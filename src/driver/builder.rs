@@ -0,0 +1,130 @@
+use libc;
+
+use driver::{Iface, Mode, Netns, Result, TunFlags};
+
+// Collects everything that has to be decided before or right after
+// TUNSETIFF. The defaults give a TUN interface without packet information
+// header whose name is picked by the kernel.
+#[derive(Debug, Clone)]
+pub struct TunBuilder {
+    name: String,
+    mode: Mode,
+    packet_info: bool,
+    multi_queue: bool,
+    vnet_hdr: bool,
+    persist: bool,
+    owner: Option<libc::uid_t>,
+    group: Option<libc::gid_t>,
+    nonblocking: bool,
+    netns: Option<Netns>,
+}
+
+impl Default for TunBuilder {
+    fn default() -> Self {
+        TunBuilder::new()
+    }
+}
+
+impl TunBuilder {
+
+    pub fn new() -> Self {
+        TunBuilder {
+            name: String::new(),
+            mode: Mode::Tun,
+            packet_info: false,
+            multi_queue: false,
+            vnet_hdr: false,
+            persist: false,
+            owner: None,
+            group: None,
+            nonblocking: false,
+            netns: None,
+        }
+    }
+
+    // Either an interface name or a template such as `tun%d`.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn packet_info(mut self, packet_info: bool) -> Self {
+        self.packet_info = packet_info;
+        self
+    }
+
+    pub fn multi_queue(mut self, multi_queue: bool) -> Self {
+        self.multi_queue = multi_queue;
+        self
+    }
+
+    pub fn vnet_hdr(mut self, vnet_hdr: bool) -> Self {
+        self.vnet_hdr = vnet_hdr;
+        self
+    }
+
+    pub fn persist(mut self, persist: bool) -> Self {
+        self.persist = persist;
+        self
+    }
+
+    pub fn owner(mut self, uid: libc::uid_t) -> Self {
+        self.owner = Some(uid);
+        self
+    }
+
+    pub fn group(mut self, gid: libc::gid_t) -> Self {
+        self.group = Some(gid);
+        self
+    }
+
+    pub fn nonblocking(mut self, nonblocking: bool) -> Self {
+        self.nonblocking = nonblocking;
+        self
+    }
+
+    // Creates the interface inside `netns` rather than in ours.
+    pub fn netns(mut self, netns: &Netns) -> Self {
+        self.netns = Some(netns.clone());
+        self
+    }
+
+    fn flags(&self) -> TunFlags {
+        let mut flags = self.mode.flags();
+        if !self.packet_info {
+            flags |= TunFlags::IFF_NO_PI;
+        }
+        if self.multi_queue {
+            flags |= TunFlags::IFF_MULTI_QUEUE;
+        }
+        if self.vnet_hdr {
+            flags |= TunFlags::IFF_VNET_HDR;
+        }
+        flags
+    }
+
+    // Persistence comes last, so that a failing step does not leave an
+    // interface behind.
+    pub fn build(self) -> Result<Iface> {
+        let mut iface = Iface::open(&self.name, self.flags(), self.netns.as_ref())?;
+
+        if let Some(uid) = self.owner {
+            iface.set_owner(uid)?;
+        }
+        if let Some(gid) = self.group {
+            iface.set_group(gid)?;
+        }
+        if self.nonblocking {
+            iface.set_nonblocking()?;
+        }
+        if self.persist {
+            iface.set_persist(true)?;
+        }
+        Ok(iface)
+    }
+}
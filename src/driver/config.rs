@@ -0,0 +1,97 @@
+use std::io;
+use std::net::Ipv4Addr;
+
+use driver::netlink::{Rule, RT_TABLE_DEFAULT, RT_TABLE_LOCAL, RT_TABLE_MAIN};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Address {
+    pub address: Ipv4Addr,
+    pub prefix_len: u8,
+}
+
+impl Address {
+    pub fn new(address: Ipv4Addr, prefix_len: u8) -> Self {
+        Address { address, prefix_len }
+    }
+}
+
+// Addressing and policy routing applied by `Iface::up`. The first address is
+// the gateway of the default route installed in `table`; the interface rule
+// sends traffic coming out of the interface to the main table, and the
+// optional catch-all rule sends everything else to `table`.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub addresses: Vec<Address>,
+    pub table: u32,
+    pub interface_rule_priority: u32,
+    pub default_rule_priority: u32,
+    pub default_rule: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            addresses: vec![Address::new(Ipv4Addr::new(172, 32, 0, 1), 24)],
+            table: 100,
+            interface_rule_priority: 10,
+            default_rule_priority: 100,
+            default_rule: true,
+        }
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+impl Config {
+
+    pub fn gateway(&self) -> Option<Ipv4Addr> {
+        self.addresses.first().map(|a| a.address)
+    }
+
+    // Checks the configuration itself, then against the rules already
+    // installed in the kernel, so that two instances never share a table or
+    // a rule priority.
+    pub fn validate(&self, rules: &[Rule]) -> io::Result<()> {
+        if self.addresses.is_empty() {
+            return Err(invalid("no address configured".to_string()));
+        }
+        for (i, a) in self.addresses.iter().enumerate() {
+            if a.prefix_len > 32 {
+                return Err(invalid(format!("invalid prefix length {} for {}", a.prefix_len, a.address)));
+            }
+            if self.addresses[..i].iter().any(|b| b.address == a.address) {
+                return Err(invalid(format!("duplicate address {}", a.address)));
+            }
+        }
+
+        match self.table {
+            0 | RT_TABLE_DEFAULT | RT_TABLE_MAIN | RT_TABLE_LOCAL =>
+                return Err(invalid(format!("routing table {} is reserved", self.table))),
+            _ => (),
+        }
+
+        if self.interface_rule_priority == 0 {
+            return Err(invalid("rule priority 0 is reserved".to_string()));
+        }
+        if self.default_rule && self.default_rule_priority <= self.interface_rule_priority {
+            return Err(invalid(format!("catch-all rule priority {} must be after interface rule priority {}",
+                                       self.default_rule_priority, self.interface_rule_priority)));
+        }
+
+        for rule in rules {
+            if rule.priority == self.interface_rule_priority
+                || (self.default_rule && rule.priority == self.default_rule_priority) {
+                return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                                          format!("rule priority {} is already in use", rule.priority)));
+            }
+            if rule.table == self.table {
+                return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                                          format!("routing table {} is already used by rule {}",
+                                                  self.table, rule.priority)));
+            }
+        }
+        Ok(())
+    }
+}
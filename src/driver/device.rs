@@ -0,0 +1,55 @@
+use std::io;
+
+use mio::Evented;
+
+use driver::{BufferPool, Mode, PacketBuf, PacketInfo};
+
+// A source and sink of packets that can be registered with a `mio::Poll`.
+// TUN devices and other backends carrying IP packets report `Mode::Tun`,
+// those carrying Ethernet frames `Mode::Tap`.
+pub trait Device: Evented {
+    fn get_mode(&self) -> Mode;
+
+    fn get_mtu(&self) -> io::Result<usize>;
+
+    // Large enough for the biggest packet `read_packet` can return.
+    fn get_buffer_size(&self) -> usize;
+
+    // When set, every packet read or written starts with a `VirtioNetHdr`.
+    fn has_vnet_hdr(&self) -> bool {
+        false
+    }
+
+    // Reads exactly one packet into `buf` and returns its length.
+    fn read_packet(&mut self, buf: &mut [u8]) -> io::Result<usize>;
+
+    // Like `read_packet`, and also returns the `PacketInfo` header that
+    // devices with one strip from the packet.
+    fn read_packet_info(&mut self, buf: &mut [u8]) -> io::Result<(usize, Option<PacketInfo>)> {
+        self.read_packet(buf).map(|len| (len, None))
+    }
+
+    // Writes `packet` as a whole and returns the number of bytes written.
+    fn write_packet(&mut self, packet: &[u8]) -> io::Result<usize>;
+
+    // Drains up to `max` packets into buffers from `pool`, appending them to
+    // `packets`, and returns how many were read. Stops early once the device
+    // would block; on any other error the packets read so far stay in
+    // `packets`.
+    fn read_batch(&mut self, pool: &BufferPool, max: usize, packets: &mut Vec<PacketBuf>)
+        -> io::Result<usize> {
+        for count in 0..max {
+            let mut buf = pool.get();
+            match self.read_packet_info(buf.as_mut_buf()) {
+                Ok((len, info)) => {
+                    buf.set_len(len);
+                    buf.set_packet_info(info);
+                    packets.push(buf);
+                },
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(count),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(max)
+    }
+}
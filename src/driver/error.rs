@@ -0,0 +1,161 @@
+use std::{error, fmt, io, result};
+
+use libc;
+
+// The configuration steps performed by `Iface::up`, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Validate,
+    AcceptLocal,
+    Link,
+    Address,
+    Route,
+    InterfaceRule,
+    DefaultRule,
+}
+
+impl Step {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Step::Validate => "validate configuration",
+            Step::AcceptLocal => "enable accept_local",
+            Step::Link => "set link up",
+            Step::Address => "add address",
+            Step::Route => "add route",
+            Step::InterfaceRule => "add interface rule",
+            Step::DefaultRule => "add catch-all rule",
+        }
+    }
+
+    // Undoing the step in `Iface::down`.
+    pub fn as_revert_str(self) -> &'static str {
+        match self {
+            Step::Validate => "validate configuration",
+            Step::AcceptLocal => "restore accept_local",
+            Step::Link => "set link down",
+            Step::Address => "delete address",
+            Step::Route => "delete route",
+            Step::InterfaceRule => "delete interface rule",
+            Step::DefaultRule => "delete catch-all rule",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    // Opening the clone device failed.
+    Open(io::Error),
+    // An ioctl on the device or an interface socket failed.
+    Ioctl { name: &'static str, error: io::Error },
+    // The interface name is too long, contains a NUL byte or is not UTF-8.
+    Name(String),
+    // A step of `Iface::up` failed.
+    Config { step: Step, error: io::Error },
+    // Reverting a step in `Iface::down` failed.
+    Revert { step: Step, error: io::Error },
+    // The operation needs privileges we do not have, usually CAP_NET_ADMIN.
+    Permission { operation: &'static str, error: io::Error },
+    Io(io::Error),
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+fn is_permission(error: &io::Error) -> bool {
+    matches!(error.raw_os_error(), Some(libc::EPERM) | Some(libc::EACCES))
+}
+
+impl Error {
+    pub fn open(error: io::Error) -> Self {
+        if is_permission(&error) {
+            Error::Permission { operation: "open", error }
+        } else {
+            Error::Open(error)
+        }
+    }
+
+    pub fn ioctl(name: &'static str, error: io::Error) -> Self {
+        if is_permission(&error) {
+            Error::Permission { operation: name, error }
+        } else {
+            Error::Ioctl { name, error }
+        }
+    }
+
+    pub fn config(step: Step, error: io::Error) -> Self {
+        if is_permission(&error) {
+            Error::Permission { operation: step.as_str(), error }
+        } else {
+            Error::Config { step, error }
+        }
+    }
+
+    pub fn revert(step: Step, error: io::Error) -> Self {
+        if is_permission(&error) {
+            Error::Permission { operation: step.as_revert_str(), error }
+        } else {
+            Error::Revert { step, error }
+        }
+    }
+
+    // Any other system call, e.g. setns or mount.
+    pub fn system(operation: &'static str, error: io::Error) -> Self {
+        if is_permission(&error) {
+            Error::Permission { operation, error }
+        } else {
+            Error::Io(error)
+        }
+    }
+
+    pub fn is_permission(&self) -> bool {
+        matches!(*self, Error::Permission { .. })
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Open(ref e) => write!(f, "open failed: {}", e),
+            Error::Ioctl { name, ref error } => write!(f, "{} failed: {}", name, error),
+            Error::Name(ref name) => write!(f, "invalid interface name {:?}", name),
+            Error::Config { step, ref error } => write!(f, "{} failed: {}", step.as_str(), error),
+            Error::Revert { step, ref error } => write!(f, "{} failed: {}", step.as_revert_str(), error),
+            Error::Permission { operation, ref error } => write!(f, "{} not permitted: {}", operation, error),
+            Error::Io(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Open(ref error)
+            | Error::Ioctl { ref error, .. }
+            | Error::Config { ref error, .. }
+            | Error::Revert { ref error, .. }
+            | Error::Permission { ref error, .. }
+            | Error::Io(ref error) => Some(error),
+            Error::Name(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::Open(error)
+            | Error::Ioctl { error, .. }
+            | Error::Config { error, .. }
+            | Error::Revert { error, .. }
+            | Error::Permission { error, .. }
+            | Error::Io(error) => error,
+            Error::Name(name) =>
+                io::Error::new(io::ErrorKind::InvalidInput, format!("invalid interface name {:?}", name)),
+        }
+    }
+}
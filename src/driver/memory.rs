@@ -0,0 +1,220 @@
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixDatagram;
+
+use mio::{Evented, Ready, Poll, PollOpt, Token};
+use mio::unix::EventedFd;
+
+use driver::{Device, Mode};
+use driver::ETHERNET_OVERHEAD;
+
+// One end of an in-memory link: what is written to one device of a `pair`
+// is read from the other. Datagram sockets keep packet boundaries, and need
+// neither root nor /dev/net/tun.
+#[derive(Debug)]
+pub struct MemoryDevice {
+    socket: UnixDatagram,
+    mode: Mode,
+    mtu: usize,
+}
+
+impl MemoryDevice {
+
+    pub fn pair(mode: Mode, mtu: usize) -> io::Result<(Self, Self)> {
+        let (a, b) = UnixDatagram::pair()?;
+        a.set_nonblocking(true)?;
+        b.set_nonblocking(true)?;
+        Ok((MemoryDevice { socket: a, mode, mtu },
+            MemoryDevice { socket: b, mode, mtu }))
+    }
+}
+
+impl Evented for MemoryDevice {
+
+    fn register(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt)
+        -> io::Result<()> {
+        EventedFd(&self.socket.as_raw_fd()).register(poll, token, interest, opts)
+    }
+
+    fn reregister(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt)
+        -> io::Result<()> {
+        EventedFd(&self.socket.as_raw_fd()).reregister(poll, token, interest, opts)
+    }
+
+    fn deregister(&self, poll: &Poll) -> io::Result<()> {
+        EventedFd(&self.socket.as_raw_fd()).deregister(poll)
+    }
+}
+
+impl Device for MemoryDevice {
+    fn get_mode(&self) -> Mode {
+        self.mode
+    }
+
+    fn get_mtu(&self) -> io::Result<usize> {
+        Ok(self.mtu)
+    }
+
+    fn get_buffer_size(&self) -> usize {
+        match self.mode {
+            Mode::Tun => self.mtu,
+            Mode::Tap => self.mtu + ETHERNET_OVERHEAD,
+        }
+    }
+
+    fn read_packet(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.socket.recv(buf)
+    }
+
+    // Like a real interface, packets larger than the MTU are refused.
+    fn write_packet(&mut self, packet: &[u8]) -> io::Result<usize> {
+        if packet.len() > self.get_buffer_size() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("packet of {} bytes exceeds the MTU", packet.len())));
+        }
+        self.socket.send(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::time::Duration;
+
+    use mio::{Events, Poll, PollOpt, Ready, Token};
+
+    use driver::{BufferPool, Device, Mode};
+    use packet::checksum::checksum;
+    use packet::icmp::{echo_reply, ICMP_ECHO_REPLY, ICMP_ECHO_REQUEST};
+    use super::MemoryDevice;
+
+    // IPv4 header without options followed by an ICMP echo request.
+    fn echo_request(payload: &[u8]) -> Vec<u8> {
+        let len = 20 + 8 + payload.len();
+        let mut packet = vec![
+            0x45, 0, (len >> 8) as u8, len as u8,
+            0, 1, 0x40, 0,
+            64, 1, 0, 0,
+            172, 32, 0, 1,
+            172, 32, 0, 2,
+            ICMP_ECHO_REQUEST, 0, 0, 0,
+            0x12, 0x34, 0, 1,
+        ];
+        packet.extend_from_slice(payload);
+
+        let ip_checksum = checksum(&packet[..20]);
+        packet[10..12].clone_from_slice(&ip_checksum.to_be_bytes());
+        let icmp_checksum = checksum(&packet[20..]);
+        packet[22..24].clone_from_slice(&icmp_checksum.to_be_bytes());
+        packet
+    }
+
+    // The icmp-echo loop: answer echo requests, pass everything else back.
+    fn respond<D: Device>(device: &mut D) -> io::Result<()> {
+        let mut buf = vec![0u8; device.get_buffer_size()];
+        let len = device.read_packet(&mut buf)?;
+        echo_reply(&mut buf[..len]);
+        device.write_packet(&buf[..len])?;
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_packet_boundaries() {
+        let (mut a, mut b) = MemoryDevice::pair(Mode::Tun, 1500).unwrap();
+        a.write_packet(&[1, 2, 3]).unwrap();
+        a.write_packet(&[4, 5]).unwrap();
+
+        let mut buf = [0u8; 1500];
+        assert_eq!(b.read_packet(&mut buf).unwrap(), 3);
+        assert_eq!(&buf[..3], &[1, 2, 3]);
+        assert_eq!(b.read_packet(&mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], &[4, 5]);
+        assert_eq!(b.read_packet(&mut buf).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    fn refuses_packets_above_mtu() {
+        let (mut a, _b) = MemoryDevice::pair(Mode::Tap, 100).unwrap();
+        assert_eq!(a.get_buffer_size(), 118);
+        assert!(a.write_packet(&[0u8; 118]).is_ok());
+        assert_eq!(a.write_packet(&[0u8; 119]).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn reads_batches_into_pooled_buffers() {
+        let (mut a, mut b) = MemoryDevice::pair(Mode::Tun, 1500).unwrap();
+        for len in 1..6 {
+            a.write_packet(&vec![len as u8; len]).unwrap();
+        }
+
+        let pool = BufferPool::new(b.get_buffer_size(), 8);
+        let mut packets = Vec::new();
+        assert_eq!(b.read_batch(&pool, 3, &mut packets).unwrap(), 3);
+        assert_eq!(b.read_batch(&pool, 3, &mut packets).unwrap(), 2);
+        assert_eq!(b.read_batch(&pool, 3, &mut packets).unwrap(), 0);
+        for (i, packet) in packets.iter().enumerate() {
+            assert_eq!(&packet[..], &vec![i as u8 + 1; i + 1][..]);
+        }
+
+        // The five packets, plus the buffer of the read that would block.
+        packets.clear();
+        assert_eq!(pool.get_idle(), 6);
+        let buf = pool.get();
+        assert_eq!((buf.len(), buf.capacity(), pool.get_idle()), (0, 1500, 5));
+    }
+
+    #[test]
+    fn becomes_readable() {
+        let (mut a, b) = MemoryDevice::pair(Mode::Tun, 1500).unwrap();
+        let poll = Poll::new().unwrap();
+        poll.register(&b, Token(0), Ready::readable(), PollOpt::level()).unwrap();
+
+        let mut events = Events::with_capacity(4);
+        poll.poll(&mut events, Some(Duration::from_millis(0))).unwrap();
+        assert!(events.is_empty());
+
+        a.write_packet(&[0x45]).unwrap();
+        poll.poll(&mut events, Some(Duration::from_secs(1))).unwrap();
+        let event = events.iter().next().unwrap();
+        assert_eq!(event.token(), Token(0));
+        assert!(event.readiness().is_readable());
+    }
+
+    #[test]
+    fn answers_icmp_echo() {
+        let (mut host, mut responder) = MemoryDevice::pair(Mode::Tun, 1500).unwrap();
+        let request = echo_request(b"lsoup");
+        host.write_packet(&request).unwrap();
+
+        respond(&mut responder).unwrap();
+
+        let mut buf = [0u8; 1500];
+        let len = host.read_packet(&mut buf).unwrap();
+        let reply = &buf[..len];
+        assert_eq!(len, request.len());
+        assert_eq!(reply[20], ICMP_ECHO_REPLY);
+        assert_eq!(&reply[12..16], &request[16..20]);
+        assert_eq!(&reply[16..20], &request[12..16]);
+        assert_eq!(&reply[24..], &request[24..]);
+        assert_eq!(checksum(&reply[..20]), 0);
+        assert_eq!(checksum(&reply[20..]), 0);
+    }
+
+    #[test]
+    fn passes_other_packets() {
+        let (mut host, mut responder) = MemoryDevice::pair(Mode::Tun, 1500).unwrap();
+        let mut request = echo_request(b"lsoup");
+        request[20] = 13;
+        host.write_packet(&request).unwrap();
+        host.write_packet(&request[..24]).unwrap();
+
+        respond(&mut responder).unwrap();
+        respond(&mut responder).unwrap();
+
+        let mut buf = [0u8; 1500];
+        let len = host.read_packet(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &request[..]);
+        let len = host.read_packet(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &request[..24]);
+    }
+}
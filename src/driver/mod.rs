@@ -1,11 +1,70 @@
 use std::io;
 
-use libc::{c_short, c_ulong};
+use libc::{c_short, c_uint, c_ulong};
 
+#[cfg(feature = "mio-wrapper")]
+mod af_packet;
+#[cfg(feature = "async-tokio")]
+mod async_tun;
+mod bpf;
+mod builder;
+mod config;
+#[cfg(feature = "mio-wrapper")]
+mod device;
+mod error;
+#[cfg(feature = "mio-wrapper")]
+mod memory;
+mod netlink;
+mod netns;
+mod pcap;
+mod pi;
+mod pool;
+mod privilege;
+#[cfg(feature = "mio-wrapper")]
+mod queue;
+mod scm;
+mod seccomp;
+mod stats;
 mod tun;
+#[cfg(feature = "uring")]
+mod uring;
+mod vnet;
+#[cfg(feature = "mio-wrapper")]
 mod wrapper;
 
-pub use driver::tun::Tun as Iface;
+#[cfg(feature = "mio-wrapper")]
+pub use driver::af_packet::PacketSocket;
+#[cfg(feature = "async-tokio")]
+pub use driver::async_tun::AsyncTun;
+pub use driver::bpf::{Filter, SockFilter};
+pub use driver::builder::TunBuilder;
+pub use driver::config::{Address, Config};
+#[cfg(feature = "mio-wrapper")]
+pub use driver::device::Device;
+pub use driver::error::{Error, Result, Step};
+#[cfg(feature = "mio-wrapper")]
+pub use driver::memory::MemoryDevice;
+#[cfg(feature = "mio-wrapper")]
+pub use driver::pcap::PcapDevice;
+pub use driver::netns::Netns;
+pub use driver::pcap::{PcapReader, PcapWriter};
+pub use driver::pi::PacketInfo;
+pub use driver::pool::{BufferPool, PacketBuf};
+pub use driver::privilege::{Capabilities, PrivilegeDrop};
+#[cfg(feature = "mio-wrapper")]
+pub use driver::queue::{DropPolicy, TxQueue, TxStats};
+pub use driver::seccomp::Seccomp;
+pub use driver::stats::{LinkState, OperState, Stats};
+pub use driver::tun::{Mode, Tun as Iface};
+#[cfg(feature = "uring")]
+pub use driver::uring::UringDevice;
+pub use driver::vnet::{complete_checksum, segment, VirtioNetHdr};
+pub use driver::vnet::{
+    VIRTIO_NET_HDR_F_DATA_VALID, VIRTIO_NET_HDR_F_NEEDS_CSUM,
+    VIRTIO_NET_HDR_GSO_ECN, VIRTIO_NET_HDR_GSO_NONE, VIRTIO_NET_HDR_GSO_TCPV4,
+    VIRTIO_NET_HDR_GSO_TCPV6, VIRTIO_NET_HDR_GSO_UDP
+};
+#[cfg(feature = "mio-wrapper")]
 pub use driver::wrapper::MioWrapper;
 
 trait IsMinusOne {
@@ -20,7 +79,7 @@ macro_rules! impl_is_minus_one {
     })*)
 }
 
-impl_is_minus_one!{ i32 }
+impl_is_minus_one!{ i32 isize }
 
 fn cvt<T: IsMinusOne>(t: T) -> io::Result<T> {
     if t.is_minus_one() {
@@ -30,20 +89,82 @@ fn cvt<T: IsMinusOne>(t: T) -> io::Result<T> {
     }
 }
 
+// Ethernet header plus one 802.1Q tag on top of the MTU for TAP frames.
+const ETHERNET_OVERHEAD: usize = 18;
+// The largest MTU the tun driver accepts, and with a vnet header the size of
+// the GSO packets it may pass.
+const MAX_PACKET_SIZE: usize = 65535;
+
 #[cfg(target_os = "linux")]
 const TUN_PATH: &str = "/dev/net/tun";
 
 bitflags! {
     struct TunFlags: c_short {
         const IFF_TUN = 0x0001;
+        const IFF_TAP = 0x0002;
+        const IFF_MULTI_QUEUE = 0x0100;
+        const IFF_ATTACH_QUEUE = 0x0200;
+        const IFF_DETACH_QUEUE = 0x0400;
+        const IFF_PERSIST = 0x0800;
         const IFF_NO_PI = 0x1000;
+        const IFF_VNET_HDR = 0x4000;
+    }
+}
+
+// Offloads the kernel may hand over to a vnet-header device, see TUNSETOFFLOAD.
+bitflags! {
+    pub struct Offload: c_uint {
+        const CSUM = 0x01;
+        const TSO4 = 0x02;
+        const TSO6 = 0x04;
+        const TSO_ECN = 0x08;
+        const UFO = 0x10;
     }
 }
 
 bitflags! {
     struct IoctlFlags: c_ulong {
         const TUNSETIFF = 0x400454ca;
+        const TUNSETPERSIST = 0x400454cb;
+        const TUNSETOWNER = 0x400454cc;
+        const TUNSETGROUP = 0x400454ce;
+        const TUNSETOFFLOAD = 0x400454d0;
+        const TUNSETQUEUE = 0x400454d9;
+        const TUNGETIFF = 0x800454d2;
+        const TUNATTACHFILTER = 0x401054d5;
+        const TUNDETACHFILTER = 0x401054d6;
+        const TUNSETCARRIER = 0x400454e2;
+        const SIOCGIFINDEX = 0x8933;
         const SIOCGIFMTU = 0x8921;
         const SIOCSIFMTU = 0x8922;
-    }   
+        const SIOCSIFHWADDR = 0x8924;
+        const SIOCGIFHWADDR = 0x8927;
+        const SIOCGIFTXQLEN = 0x8942;
+        const SIOCSIFTXQLEN = 0x8943;
+    }
+}
+
+impl IoctlFlags {
+    fn name(self) -> &'static str {
+        match self {
+            IoctlFlags::TUNSETIFF => "TUNSETIFF",
+            IoctlFlags::TUNSETPERSIST => "TUNSETPERSIST",
+            IoctlFlags::TUNSETOWNER => "TUNSETOWNER",
+            IoctlFlags::TUNSETGROUP => "TUNSETGROUP",
+            IoctlFlags::TUNSETOFFLOAD => "TUNSETOFFLOAD",
+            IoctlFlags::TUNSETQUEUE => "TUNSETQUEUE",
+            IoctlFlags::TUNGETIFF => "TUNGETIFF",
+            IoctlFlags::TUNATTACHFILTER => "TUNATTACHFILTER",
+            IoctlFlags::TUNDETACHFILTER => "TUNDETACHFILTER",
+            IoctlFlags::TUNSETCARRIER => "TUNSETCARRIER",
+            IoctlFlags::SIOCGIFINDEX => "SIOCGIFINDEX",
+            IoctlFlags::SIOCGIFMTU => "SIOCGIFMTU",
+            IoctlFlags::SIOCSIFMTU => "SIOCSIFMTU",
+            IoctlFlags::SIOCSIFHWADDR => "SIOCSIFHWADDR",
+            IoctlFlags::SIOCGIFHWADDR => "SIOCGIFHWADDR",
+            IoctlFlags::SIOCGIFTXQLEN => "SIOCGIFTXQLEN",
+            IoctlFlags::SIOCSIFTXQLEN => "SIOCSIFTXQLEN",
+            _ => "ioctl",
+        }
+    }
 }
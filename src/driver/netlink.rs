@@ -0,0 +1,486 @@
+use std::{cmp, io, mem, slice};
+use std::net::Ipv4Addr;
+use std::os::unix::io::RawFd;
+
+use libc;
+use libc::{c_int, c_uint};
+
+use driver::cvt;
+
+const NETLINK_ROUTE: c_int = 0;
+
+const NLMSG_ERROR: u16 = 0x2;
+const NLMSG_DONE: u16 = 0x3;
+
+const NLM_F_REQUEST: u16 = 0x001;
+const NLM_F_ACK: u16 = 0x004;
+const NLM_F_DUMP: u16 = 0x300;
+const NLM_F_EXCL: u16 = 0x200;
+const NLM_F_CREATE: u16 = 0x400;
+
+const RTM_NEWLINK: u16 = 16;
+const RTM_GETLINK: u16 = 18;
+const RTM_NEWADDR: u16 = 20;
+const RTM_DELADDR: u16 = 21;
+const RTM_NEWROUTE: u16 = 24;
+const RTM_DELROUTE: u16 = 25;
+const RTM_NEWRULE: u16 = 32;
+const RTM_DELRULE: u16 = 33;
+const RTM_GETRULE: u16 = 34;
+
+const IFLA_IFNAME: u16 = 3;
+const IFLA_OPERSTATE: u16 = 16;
+const IFLA_STATS64: u16 = 23;
+const IFLA_NET_NS_FD: u16 = 28;
+const IFLA_CARRIER: u16 = 33;
+
+const IFA_ADDRESS: u16 = 1;
+const IFA_LOCAL: u16 = 2;
+
+const RTA_OIF: u16 = 4;
+const RTA_GATEWAY: u16 = 5;
+const RTA_TABLE: u16 = 15;
+
+const FRA_IFNAME: u16 = 3;
+const FRA_PRIORITY: u16 = 6;
+const FRA_TABLE: u16 = 15;
+
+const RTPROT_BOOT: u8 = 3;
+const RT_SCOPE_UNIVERSE: u8 = 0;
+const RTN_UNICAST: u8 = 1;
+const RT_TABLE_UNSPEC: u8 = 0;
+const FR_ACT_TO_TBL: u8 = 1;
+
+pub const RT_TABLE_DEFAULT: u32 = 253;
+pub const RT_TABLE_MAIN: u32 = 254;
+pub const RT_TABLE_LOCAL: u32 = 255;
+
+// An IPv4 policy routing rule as reported by the kernel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub priority: u32,
+    pub table: u32,
+}
+
+// An interface as reported by RTM_GETLINK.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    pub flags: u32,
+    pub carrier: bool,
+    // IF_OPER_UNKNOWN up to IF_OPER_UP, as in RFC 2863.
+    pub oper_state: u8,
+    // The leading counters of struct rtnl_link_stats64: rx and tx packets,
+    // bytes, errors and dropped.
+    pub stats: [u64; 8],
+}
+
+#[repr(C)]
+struct NlMsgHdr {
+    nlmsg_len: u32,
+    nlmsg_type: u16,
+    nlmsg_flags: u16,
+    nlmsg_seq: u32,
+    nlmsg_pid: u32,
+}
+
+#[repr(C)]
+struct IfInfoMsg {
+    ifi_family: u8,
+    ifi_pad: u8,
+    ifi_type: u16,
+    ifi_index: i32,
+    ifi_flags: u32,
+    ifi_change: u32,
+}
+
+#[repr(C)]
+struct IfAddrMsg {
+    ifa_family: u8,
+    ifa_prefixlen: u8,
+    ifa_flags: u8,
+    ifa_scope: u8,
+    ifa_index: u32,
+}
+
+#[repr(C)]
+struct RtMsg {
+    rtm_family: u8,
+    rtm_dst_len: u8,
+    rtm_src_len: u8,
+    rtm_tos: u8,
+    rtm_table: u8,
+    rtm_protocol: u8,
+    rtm_scope: u8,
+    rtm_type: u8,
+    rtm_flags: u32,
+}
+
+#[repr(C)]
+struct FibRuleHdr {
+    family: u8,
+    dst_len: u8,
+    src_len: u8,
+    tos: u8,
+    table: u8,
+    res1: u8,
+    res2: u8,
+    action: u8,
+    flags: u32,
+}
+
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+// Walks the rtattr list in `buf`, passing each attribute type and payload to `f`.
+fn attrs<F>(mut buf: &[u8], mut f: F) where F: FnMut(u16, &[u8]) {
+    while buf.len() >= 4 {
+        let len = u16::from_ne_bytes([buf[0], buf[1]]) as usize;
+        let ty = u16::from_ne_bytes([buf[2], buf[3]]);
+        if len < 4 || len > buf.len() {
+            return;
+        }
+        f(ty, &buf[4..len]);
+        buf = &buf[cmp::min(align(len), buf.len())..];
+    }
+}
+
+fn as_bytes<T>(t: &T) -> &[u8] {
+    unsafe { slice::from_raw_parts(t as *const T as *const u8, mem::size_of::<T>()) }
+}
+
+struct Message {
+    buf: Vec<u8>,
+}
+
+impl Message {
+    fn new(ty: u16, flags: u16) -> Self {
+        Message::with_flags(ty, NLM_F_REQUEST | NLM_F_ACK | flags)
+    }
+
+    fn dump(ty: u16) -> Self {
+        Message::with_flags(ty, NLM_F_REQUEST | NLM_F_DUMP)
+    }
+
+    fn with_flags(ty: u16, flags: u16) -> Self {
+        let hdr = NlMsgHdr {
+            nlmsg_len: 0,
+            nlmsg_type: ty,
+            nlmsg_flags: flags,
+            nlmsg_seq: 0,
+            nlmsg_pid: 0,
+        };
+        let mut msg = Message { buf: Vec::with_capacity(128) };
+        msg.push(as_bytes(&hdr));
+        msg
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+        let len = align(self.buf.len());
+        self.buf.resize(len, 0);
+    }
+
+    fn attr(&mut self, ty: u16, payload: &[u8]) {
+        let len = (4 + payload.len()) as u16;
+        self.buf.extend_from_slice(as_bytes(&len));
+        self.buf.extend_from_slice(as_bytes(&ty));
+        self.push(payload);
+    }
+
+    fn finish(&mut self, seq: u32) -> &[u8] {
+        let len = self.buf.len() as u32;
+        self.buf[0..4].clone_from_slice(as_bytes(&len));
+        self.buf[8..12].clone_from_slice(as_bytes(&seq));
+        &self.buf
+    }
+}
+
+#[derive(Debug)]
+pub struct Netlink {
+    fd: RawFd,
+    seq: u32,
+}
+
+impl Drop for Netlink {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+impl Netlink {
+
+    pub fn new() -> io::Result<Self> {
+        let fd = cvt(unsafe {
+            libc::socket(libc::AF_NETLINK, libc::SOCK_RAW | libc::SOCK_CLOEXEC, NETLINK_ROUTE)
+        })?;
+        let netlink = Netlink { fd, seq: 0 };
+
+        let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        cvt(unsafe {
+            libc::bind(fd,
+                       &addr as *const _ as *const libc::sockaddr,
+                       mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t)
+        })?;
+
+        Ok(netlink)
+    }
+
+    fn request(&mut self, msg: &mut Message) -> io::Result<()> {
+        self.transact(msg, |_, _| ())
+    }
+
+    // Sends `msg` and passes every reply to `f` until the kernel acknowledges
+    // the request or finishes the dump.
+    fn transact<F>(&mut self, msg: &mut Message, mut f: F) -> io::Result<()>
+        where F: FnMut(u16, &[u8]) {
+        self.seq = self.seq.wrapping_add(1);
+        let seq = self.seq;
+        let buf = msg.finish(seq);
+
+        let len = unsafe { libc::send(self.fd, buf.as_ptr() as *const _, buf.len(), 0) };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut buf = vec![0u8; 16384];
+        loop {
+            let len = unsafe { libc::recv(self.fd, buf.as_mut_ptr() as *mut _, buf.len(), 0) };
+            if len < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let mut offset = 0;
+            let len = len as usize;
+            while offset + mem::size_of::<NlMsgHdr>() <= len {
+                let hdr = unsafe { &*(buf[offset..].as_ptr() as *const NlMsgHdr) };
+                let msg_len = hdr.nlmsg_len as usize;
+                if msg_len < mem::size_of::<NlMsgHdr>() || offset + msg_len > len {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "truncated netlink message"));
+                }
+
+                let payload = &buf[offset + mem::size_of::<NlMsgHdr>()..offset + msg_len];
+                if hdr.nlmsg_seq == seq {
+                    match hdr.nlmsg_type {
+                        NLMSG_ERROR => {
+                            let errno = unsafe { *(payload.as_ptr() as *const c_int) };
+                            return if errno == 0 {
+                                Ok(())
+                            } else {
+                                Err(io::Error::from_raw_os_error(-errno))
+                            };
+                        },
+                        NLMSG_DONE => return Ok(()),
+                        ty => f(ty, payload),
+                    }
+                }
+                offset += align(msg_len);
+            }
+        }
+    }
+
+    pub fn set_link_up(&mut self, index: c_uint, up: bool) -> io::Result<()> {
+        let ifi = IfInfoMsg {
+            ifi_family: libc::AF_UNSPEC as u8,
+            ifi_pad: 0,
+            ifi_type: 0,
+            ifi_index: index as i32,
+            ifi_flags: if up { libc::IFF_UP as u32 } else { 0 },
+            ifi_change: libc::IFF_UP as u32,
+        };
+
+        let mut msg = Message::new(RTM_NEWLINK, 0);
+        msg.push(as_bytes(&ifi));
+        self.request(&mut msg)
+    }
+
+    // Moves the interface into the network namespace `netns` refers to.
+    pub fn set_link_netns(&mut self, index: c_uint, netns: RawFd) -> io::Result<()> {
+        let ifi = IfInfoMsg {
+            ifi_family: libc::AF_UNSPEC as u8,
+            ifi_pad: 0,
+            ifi_type: 0,
+            ifi_index: index as i32,
+            ifi_flags: 0,
+            ifi_change: 0,
+        };
+
+        let mut msg = Message::new(RTM_NEWLINK, 0);
+        msg.push(as_bytes(&ifi));
+        msg.attr(IFLA_NET_NS_FD, as_bytes(&(netns as u32)));
+        self.request(&mut msg)
+    }
+
+    pub fn link(&mut self, name: &str) -> io::Result<Link> {
+        let ifi = IfInfoMsg {
+            ifi_family: libc::AF_UNSPEC as u8,
+            ifi_pad: 0,
+            ifi_type: 0,
+            ifi_index: 0,
+            ifi_flags: 0,
+            ifi_change: 0,
+        };
+
+        let mut msg = Message::new(RTM_GETLINK, 0);
+        msg.push(as_bytes(&ifi));
+        let mut ifname = name.as_bytes().to_vec();
+        ifname.push(0);
+        msg.attr(IFLA_IFNAME, &ifname);
+
+        let mut link = None;
+        self.transact(&mut msg, |ty, payload| {
+            if ty != RTM_NEWLINK || payload.len() < mem::size_of::<IfInfoMsg>() {
+                return;
+            }
+
+            let ifi = unsafe { &*(payload.as_ptr() as *const IfInfoMsg) };
+            let mut found = Link { flags: ifi.ifi_flags, carrier: false, oper_state: 0, stats: [0; 8] };
+            attrs(&payload[mem::size_of::<IfInfoMsg>()..], |ty, value| {
+                match ty {
+                    IFLA_CARRIER if !value.is_empty() => found.carrier = value[0] != 0,
+                    IFLA_OPERSTATE if !value.is_empty() => found.oper_state = value[0],
+                    IFLA_STATS64 if value.len() >= 64 => {
+                        for (counter, bytes) in found.stats.iter_mut().zip(value.chunks(8)) {
+                            let mut raw = [0u8; 8];
+                            raw.clone_from_slice(bytes);
+                            *counter = u64::from_ne_bytes(raw);
+                        }
+                    },
+                    _ => (),
+                }
+            });
+            link = Some(found);
+        })?;
+        link.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no interface {}", name)))
+    }
+
+    pub fn add_address(&mut self, index: c_uint, addr: Ipv4Addr, prefix_len: u8) -> io::Result<()> {
+        self.address(RTM_NEWADDR, NLM_F_CREATE | NLM_F_EXCL, index, addr, prefix_len)
+    }
+
+    pub fn del_address(&mut self, index: c_uint, addr: Ipv4Addr, prefix_len: u8) -> io::Result<()> {
+        self.address(RTM_DELADDR, 0, index, addr, prefix_len)
+    }
+
+    fn address(&mut self, ty: u16, flags: u16, index: c_uint, addr: Ipv4Addr, prefix_len: u8)
+        -> io::Result<()> {
+        let ifa = IfAddrMsg {
+            ifa_family: libc::AF_INET as u8,
+            ifa_prefixlen: prefix_len,
+            ifa_flags: 0,
+            ifa_scope: RT_SCOPE_UNIVERSE,
+            ifa_index: index,
+        };
+
+        let mut msg = Message::new(ty, flags);
+        msg.push(as_bytes(&ifa));
+        msg.attr(IFA_LOCAL, &addr.octets());
+        msg.attr(IFA_ADDRESS, &addr.octets());
+        self.request(&mut msg)
+    }
+
+    pub fn add_default_route(&mut self, index: c_uint, gateway: Ipv4Addr, table: u32) -> io::Result<()> {
+        self.default_route(RTM_NEWROUTE, NLM_F_CREATE | NLM_F_EXCL, index, gateway, table)
+    }
+
+    pub fn del_default_route(&mut self, index: c_uint, gateway: Ipv4Addr, table: u32) -> io::Result<()> {
+        self.default_route(RTM_DELROUTE, 0, index, gateway, table)
+    }
+
+    fn default_route(&mut self, ty: u16, flags: u16, index: c_uint, gateway: Ipv4Addr, table: u32)
+        -> io::Result<()> {
+        let rtm = RtMsg {
+            rtm_family: libc::AF_INET as u8,
+            rtm_dst_len: 0,
+            rtm_src_len: 0,
+            rtm_tos: 0,
+            rtm_table: if table < 256 { table as u8 } else { RT_TABLE_UNSPEC },
+            rtm_protocol: RTPROT_BOOT,
+            rtm_scope: RT_SCOPE_UNIVERSE,
+            rtm_type: RTN_UNICAST,
+            rtm_flags: 0,
+        };
+
+        let mut msg = Message::new(ty, flags);
+        msg.push(as_bytes(&rtm));
+        msg.attr(RTA_GATEWAY, &gateway.octets());
+        msg.attr(RTA_OIF, as_bytes(&index));
+        msg.attr(RTA_TABLE, as_bytes(&table));
+        self.request(&mut msg)
+    }
+
+    // `iif` restricts the rule to packets received on that interface, like `ip rule add iif`
+    pub fn add_rule(&mut self, priority: u32, table: u32, iif: Option<&str>) -> io::Result<()> {
+        self.rule(RTM_NEWRULE, NLM_F_CREATE | NLM_F_EXCL, priority, table, iif)
+    }
+
+    pub fn del_rule(&mut self, priority: u32, table: u32, iif: Option<&str>) -> io::Result<()> {
+        self.rule(RTM_DELRULE, 0, priority, table, iif)
+    }
+
+    fn rule(&mut self, ty: u16, flags: u16, priority: u32, table: u32, iif: Option<&str>)
+        -> io::Result<()> {
+        let frh = FibRuleHdr {
+            family: libc::AF_INET as u8,
+            dst_len: 0,
+            src_len: 0,
+            tos: 0,
+            table: if table < 256 { table as u8 } else { RT_TABLE_UNSPEC },
+            res1: 0,
+            res2: 0,
+            action: FR_ACT_TO_TBL,
+            flags: 0,
+        };
+
+        let mut msg = Message::new(ty, flags);
+        msg.push(as_bytes(&frh));
+        msg.attr(FRA_PRIORITY, as_bytes(&priority));
+        msg.attr(FRA_TABLE, as_bytes(&table));
+        if let Some(name) = iif {
+            let mut name = name.as_bytes().to_vec();
+            name.push(0);
+            msg.attr(FRA_IFNAME, &name);
+        }
+        self.request(&mut msg)
+    }
+
+    pub fn rules(&mut self) -> io::Result<Vec<Rule>> {
+        let frh = FibRuleHdr {
+            family: libc::AF_INET as u8,
+            dst_len: 0,
+            src_len: 0,
+            tos: 0,
+            table: 0,
+            res1: 0,
+            res2: 0,
+            action: 0,
+            flags: 0,
+        };
+
+        let mut msg = Message::dump(RTM_GETRULE);
+        msg.push(as_bytes(&frh));
+
+        let mut rules = Vec::new();
+        self.transact(&mut msg, |ty, payload| {
+            if ty != RTM_NEWRULE || payload.len() < mem::size_of::<FibRuleHdr>() {
+                return;
+            }
+
+            let mut rule = Rule { priority: 0, table: payload[4] as u32 };
+            attrs(&payload[mem::size_of::<FibRuleHdr>()..], |ty, value| {
+                if value.len() < 4 {
+                    return;
+                }
+                let value = u32::from_ne_bytes([value[0], value[1], value[2], value[3]]);
+                match ty {
+                    FRA_PRIORITY => rule.priority = value,
+                    FRA_TABLE => rule.table = value,
+                    _ => (),
+                }
+            });
+            rules.push(rule);
+        })?;
+        Ok(rules)
+    }
+}
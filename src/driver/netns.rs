@@ -0,0 +1,138 @@
+/*
+ * Reference:
+ *  https://man7.org/linux/man-pages/man8/ip-netns.8.html
+ *  https://man7.org/linux/man-pages/man2/setns.2.html
+ *
+ * Date: Oct 18 CST 2026
+ */
+
+use std::{ffi, fs, io, panic, ptr, thread};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::Arc;
+
+use libc;
+
+use driver::{cvt, Error, Result};
+
+// Where `ip netns` keeps its names, so that both see the same namespaces.
+const NETNS_RUN_DIR: &str = "/var/run/netns";
+
+fn path(name: &str) -> Result<String> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') || name.contains('\0') {
+        return Err(Error::Io(io::Error::new(io::ErrorKind::InvalidInput,
+                                            format!("invalid namespace name {:?}", name))));
+    }
+    Ok(format!("{}/{}", NETNS_RUN_DIR, name))
+}
+
+// Makes NETNS_RUN_DIR a shared mount, binding it onto itself first if it is
+// no mount point yet, as `ip netns add` does. The bind mounts under it then
+// propagate to other mount namespaces, and a process unsharing its mounts
+// later cannot keep a namespace alive after `delete`.
+fn share_run_dir() -> Result<()> {
+    let dir = ffi::CString::new(NETNS_RUN_DIR).unwrap();
+    let none = ffi::CString::new("none").unwrap();
+    let share = || cvt(unsafe {
+        libc::mount(none.as_ptr(), dir.as_ptr(), none.as_ptr(),
+                    libc::MS_SHARED | libc::MS_REC, ptr::null())
+    });
+    match share() {
+        Err(ref e) if e.raw_os_error() == Some(libc::EINVAL) => {
+            cvt(unsafe {
+                libc::mount(dir.as_ptr(), dir.as_ptr(), none.as_ptr(),
+                            libc::MS_BIND | libc::MS_REC, ptr::null())
+            }).and_then(|_| share())
+        },
+        result => result,
+    }.map(|_| ()).map_err(|e| Error::system("mount", e))
+}
+
+// A named network namespace. An `Iface` built or moved there keeps a handle
+// and configures addresses, routes and rules inside it, so that `up` cannot
+// touch the routing of the host.
+#[derive(Debug, Clone)]
+pub struct Netns {
+    name: String,
+    file: Arc<fs::File>,
+}
+
+impl Netns {
+
+    // Like `ip netns add`: a fresh namespace, kept alive by a bind mount of
+    // its nsfs file under NETNS_RUN_DIR.
+    pub fn create(name: &str) -> Result<Self> {
+        let path = path(name)?;
+        fs::create_dir_all(NETNS_RUN_DIR)?;
+        share_run_dir()?;
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o444)
+            .open(&path)?;
+
+        // Only this thread enters the new namespace, and it ends right after.
+        let source = ffi::CString::new("/proc/thread-self/ns/net").unwrap();
+        let target = ffi::CString::new(path.as_str()).unwrap();
+        let result = thread::spawn(move || {
+            cvt(unsafe { libc::unshare(libc::CLONE_NEWNET) })
+                .map_err(|e| Error::system("unshare", e))?;
+            cvt(unsafe {
+                libc::mount(source.as_ptr(), target.as_ptr(), ptr::null(),
+                            libc::MS_BIND, ptr::null())
+            }).map_err(|e| Error::system("mount", e))?;
+            Ok(())
+        }).join().unwrap_or_else(|panic| panic::resume_unwind(panic));
+
+        if let Err(e) = result {
+            let _ = fs::remove_file(&path);
+            return Err(e);
+        }
+        Netns::open(name)
+    }
+
+    pub fn open(name: &str) -> Result<Self> {
+        let file = fs::File::open(path(name)?).map_err(Error::open)?;
+        Ok(Netns {
+            name: name.to_string(),
+            file: Arc::new(file),
+        })
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    // Like `ip netns delete`: removes the name. The namespace itself lives
+    // on while any process or fd, this handle and its clones included, still
+    // refers to it.
+    pub fn delete(self) -> Result<()> {
+        let path = path(&self.name)?;
+        let target = ffi::CString::new(path.as_str()).unwrap();
+        cvt(unsafe { libc::umount2(target.as_ptr(), libc::MNT_DETACH) })
+            .map_err(|e| Error::system("umount", e))?;
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    // Runs `f` on a thread that joined this namespace, so that the sockets
+    // and /proc/sys/net files it opens belong there. The rest of the process
+    // stays where it is.
+    pub(super) fn run<T, F>(&self, f: F) -> Result<T>
+        where T: Send, F: FnOnce() -> Result<T> + Send {
+        let fd = self.file.as_raw_fd();
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                cvt(unsafe { libc::setns(fd, libc::CLONE_NEWNET) })
+                    .map_err(|e| Error::system("setns", e))?;
+                f()
+            }).join().unwrap_or_else(|panic| panic::resume_unwind(panic))
+        })
+    }
+}
+
+impl AsRawFd for Netns {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
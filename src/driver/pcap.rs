@@ -0,0 +1,581 @@
+/*
+ * Reference:
+ *  https://wiki.wireshark.org/Development/LibpcapFileFormat
+ *  https://ietf-opsawg-wg.github.io/draft-ietf-opsawg-pcap/draft-ietf-opsawg-pcapng.html
+ *  https://www.tcpdump.org/linktypes.html
+ *
+ * Date: Oct 18 CST 2026
+ */
+
+use std::{cmp, io};
+use std::io::{Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+#[cfg(feature = "mio-wrapper")]
+use std::fs;
+#[cfg(feature = "mio-wrapper")]
+use std::path::Path;
+
+#[cfg(feature = "mio-wrapper")]
+use mio::{Evented, Ready, Registration, Poll, PollOpt, SetReadiness, Token};
+
+use driver::Mode;
+#[cfg(feature = "mio-wrapper")]
+use driver::Device;
+use driver::ETHERNET_OVERHEAD;
+
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+// DLT_RAW as written by some BSDs and OpenBSD.
+const LINKTYPE_RAW_BSD: u32 = 12;
+const LINKTYPE_RAW_OPENBSD: u32 = 14;
+const LINKTYPE_IPV4: u32 = 228;
+const LINKTYPE_IPV6: u32 = 229;
+
+const PCAP_MAGIC: u32 = 0xa1b2c3d4;
+const PCAP_MAGIC_NANOS: u32 = 0xa1b23c4d;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b3c4d;
+
+const PCAPNG_SECTION_HEADER: u32 = 0x0a0d0d0a;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 1;
+const PCAPNG_OBSOLETE_PACKET: u32 = 2;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+
+// Captures are not limited by an MTU, but an IP packet never exceeds 64K.
+const MAX_PACKET_SIZE: usize = 65535;
+// Lengths read from a file are checked against these before allocating: a
+// record holds one packet, a pcapng block also its fixed fields and options.
+const MAX_RECORD_SIZE: usize = MAX_PACKET_SIZE + ETHERNET_OVERHEAD;
+const MAX_BLOCK_SIZE: usize = MAX_RECORD_SIZE + 65536;
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn linktype_mode(linktype: u32) -> io::Result<Mode> {
+    match linktype {
+        LINKTYPE_ETHERNET => Ok(Mode::Tap),
+        LINKTYPE_RAW | LINKTYPE_RAW_BSD | LINKTYPE_RAW_OPENBSD | LINKTYPE_IPV4 | LINKTYPE_IPV6 => Ok(Mode::Tun),
+        _ => Err(io::Error::new(io::ErrorKind::Unsupported, format!("unsupported link type {}", linktype))),
+    }
+}
+
+fn u16_at(buf: &[u8], offset: usize, big_endian: bool) -> u16 {
+    let bytes = [buf[offset], buf[offset + 1]];
+    if big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) }
+}
+
+fn u32_at(buf: &[u8], offset: usize, big_endian: bool) -> u32 {
+    let bytes = [buf[offset], buf[offset + 1], buf[offset + 2], buf[offset + 3]];
+    if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) }
+}
+
+// Like `read_exact`, but a clean end of file before the first byte is `false`.
+fn read_or_eof<R: Read>(input: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match input.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated capture")),
+            Ok(len) => filled += len,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+// What one pcapng block turned out to be.
+enum Block {
+    Packet(Vec<u8>),
+    Other,
+    End,
+}
+
+// Interfaces described so far in the current pcapng section, or the single
+// link of a classic pcap file.
+#[derive(Debug, Clone, Copy)]
+struct Interface {
+    mode: Mode,
+    snaplen: usize,
+}
+
+// Reads packets from a classic pcap or a pcapng file, in either byte order.
+// All interfaces of the file must carry the same kind of packets, raw IP or
+// Ethernet, which `get_mode` reports.
+#[derive(Debug)]
+pub struct PcapReader<R> {
+    input: R,
+    big_endian: bool,
+    pcapng: bool,
+    mode: Mode,
+    interfaces: Vec<Interface>,
+}
+
+impl<R: Read> PcapReader<R> {
+
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let mut magic = [0u8; 4];
+        input.read_exact(&mut magic)?;
+
+        match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+            (PCAP_MAGIC, _) | (PCAP_MAGIC_NANOS, _) => PcapReader::classic(input, false),
+            (_, PCAP_MAGIC) | (_, PCAP_MAGIC_NANOS) => PcapReader::classic(input, true),
+            (PCAPNG_SECTION_HEADER, _) => PcapReader::pcapng(input),
+            _ => Err(invalid("not a pcap or pcapng file".to_string())),
+        }
+    }
+
+    /*
+        Global header, after the magic number:
+            version major (2), version minor (2), thiszone (4), sigfigs (4),
+            snaplen (4), network (4)
+    */
+    fn classic(mut input: R, big_endian: bool) -> io::Result<Self> {
+        let mut header = [0u8; 20];
+        input.read_exact(&mut header)?;
+
+        let snaplen = u32_at(&header, 12, big_endian) as usize;
+        let mode = linktype_mode(u32_at(&header, 16, big_endian) & 0xffff)?;
+        Ok(PcapReader {
+            input,
+            big_endian,
+            pcapng: false,
+            mode,
+            interfaces: vec![Interface { mode, snaplen }],
+        })
+    }
+
+    // Reads the first section header and everything up to the first
+    // interface description, which decides the mode.
+    fn pcapng(mut input: R) -> io::Result<Self> {
+        let mut header = [0u8; 8];
+        input.read_exact(&mut header)?;
+
+        let mut reader = PcapReader {
+            input,
+            big_endian: false,
+            pcapng: true,
+            mode: Mode::Tun,
+            interfaces: Vec::new(),
+        };
+        reader.section_header(header)?;
+
+        while reader.interfaces.is_empty() {
+            match reader.block()? {
+                Block::Packet(_) => return Err(invalid("packet before any interface description".to_string())),
+                Block::End => return Err(invalid("no interface description".to_string())),
+                Block::Other => (),
+            }
+        }
+        reader.mode = reader.interfaces[0].mode;
+        Ok(reader)
+    }
+
+    pub fn get_mode(&self) -> Mode {
+        self.mode
+    }
+
+    /*
+        Section header block, after the block type:
+            block total length (4), byte-order magic (4), version (4),
+            section length (8), options, block total length (4)
+
+        `header` holds the length and the magic, the byte order may change
+        with every section.
+    */
+    fn section_header(&mut self, header: [u8; 8]) -> io::Result<()> {
+        self.big_endian = match u32::from_le_bytes([header[4], header[5], header[6], header[7]]) {
+            PCAPNG_BYTE_ORDER_MAGIC => false,
+            _ if u32_at(&header, 4, true) == PCAPNG_BYTE_ORDER_MAGIC => true,
+            _ => return Err(invalid("bad pcapng byte-order magic".to_string())),
+        };
+        let len = u32_at(&header, 0, self.big_endian) as usize;
+        if !(28..=MAX_BLOCK_SIZE).contains(&len) || !len.is_multiple_of(4) {
+            return Err(invalid(format!("bad pcapng section length {}", len)));
+        }
+
+        let mut rest = vec![0u8; len - 12];
+        self.input.read_exact(&mut rest)?;
+        self.interfaces.clear();
+        Ok(())
+    }
+
+    // Reads one pcapng block. Only a clean end of file between blocks is
+    // `Block::End`, a truncated block is an `UnexpectedEof` error.
+    fn block(&mut self) -> io::Result<Block> {
+        let mut header = [0u8; 8];
+        if !read_or_eof(&mut self.input, &mut header)? {
+            return Ok(Block::End);
+        }
+
+        let ty = u32_at(&header, 0, self.big_endian);
+        if ty == PCAPNG_SECTION_HEADER {
+            let mut section = [0u8; 8];
+            section[..4].clone_from_slice(&header[4..8]);
+            self.input.read_exact(&mut section[4..])?;
+            return self.section_header(section).map(|_| Block::Other);
+        }
+
+        let len = u32_at(&header, 4, self.big_endian) as usize;
+        if !(12..=MAX_BLOCK_SIZE).contains(&len) || !len.is_multiple_of(4) {
+            return Err(invalid(format!("bad pcapng block length {}", len)));
+        }
+        let mut body = vec![0u8; len - 8];
+        self.input.read_exact(&mut body)?;
+        let body = &body[..len - 12];
+        let big_endian = self.big_endian;
+
+        let (interface, offset, caplen) = match ty {
+            PCAPNG_INTERFACE_DESCRIPTION if body.len() >= 8 => {
+                let mode = linktype_mode(u16_at(body, 0, big_endian) as u32)?;
+                let snaplen = u32_at(body, 4, big_endian) as usize;
+                self.interfaces.push(Interface { mode, snaplen });
+                return Ok(Block::Other);
+            },
+            PCAPNG_ENHANCED_PACKET if body.len() >= 20 =>
+                (u32_at(body, 0, big_endian) as usize, 20, u32_at(body, 12, big_endian) as usize),
+            PCAPNG_OBSOLETE_PACKET if body.len() >= 20 =>
+                (u16_at(body, 0, big_endian) as usize, 20, u32_at(body, 12, big_endian) as usize),
+            PCAPNG_SIMPLE_PACKET if body.len() >= 4 => {
+                let snaplen = self.interfaces.first().map_or(0, |i| i.snaplen);
+                let caplen = u32_at(body, 0, big_endian) as usize;
+                (0, 4, if snaplen == 0 { caplen } else { cmp::min(caplen, snaplen) })
+            },
+            PCAPNG_INTERFACE_DESCRIPTION | PCAPNG_ENHANCED_PACKET | PCAPNG_OBSOLETE_PACKET
+                | PCAPNG_SIMPLE_PACKET => return Err(invalid(format!("short pcapng block {}", ty))),
+            _ => return Ok(Block::Other),
+        };
+
+        match self.interfaces.get(interface) {
+            Some(i) if i.mode == self.mode => (),
+            Some(_) => return Err(invalid(format!("interface {} has a different link type", interface))),
+            None => return Err(invalid(format!("packet on undescribed interface {}", interface))),
+        }
+        match body.get(offset..offset + caplen) {
+            Some(packet) => Ok(Block::Packet(packet.to_vec())),
+            None => Err(invalid(format!("packet of {} bytes overruns its block", caplen))),
+        }
+    }
+
+    /*
+        Record header of a classic pcap file:
+            ts_sec (4), ts_usec or ts_nsec (4), incl_len (4), orig_len (4)
+    */
+    pub fn next_packet(&mut self) -> io::Result<Option<Vec<u8>>> {
+        if self.pcapng {
+            loop {
+                match self.block()? {
+                    Block::Packet(packet) => return Ok(Some(packet)),
+                    Block::Other => (),
+                    Block::End => return Ok(None),
+                }
+            }
+        }
+
+        let mut header = [0u8; 16];
+        if !read_or_eof(&mut self.input, &mut header)? {
+            return Ok(None);
+        }
+        let len = u32_at(&header, 8, self.big_endian) as usize;
+        if len > MAX_RECORD_SIZE {
+            return Err(invalid(format!("record of {} bytes exceeds {}", len, MAX_RECORD_SIZE)));
+        }
+
+        let mut packet = vec![0u8; len];
+        self.input.read_exact(&mut packet)?;
+        Ok(Some(packet))
+    }
+}
+
+// Writes a classic little-endian pcap file with microsecond timestamps.
+#[derive(Debug)]
+pub struct PcapWriter<W: Write> {
+    output: W,
+}
+
+impl<W: Write> PcapWriter<W> {
+
+    pub fn new(mut output: W, mode: Mode) -> io::Result<Self> {
+        let linktype = match mode {
+            Mode::Tun => LINKTYPE_RAW,
+            Mode::Tap => LINKTYPE_ETHERNET,
+        };
+
+        let mut header = Vec::with_capacity(24);
+        header.extend_from_slice(&PCAP_MAGIC.to_le_bytes());
+        header.extend_from_slice(&2u16.to_le_bytes());
+        header.extend_from_slice(&4u16.to_le_bytes());
+        header.extend_from_slice(&[0u8; 8]);
+        header.extend_from_slice(&(MAX_RECORD_SIZE as u32).to_le_bytes());
+        header.extend_from_slice(&linktype.to_le_bytes());
+        output.write_all(&header)?;
+        output.flush()?;
+
+        Ok(PcapWriter { output })
+    }
+
+    // Every record is flushed, so the file stays readable if we get killed.
+    pub fn write_packet(&mut self, packet: &[u8]) -> io::Result<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+
+        let mut record = Vec::with_capacity(16 + packet.len());
+        record.extend_from_slice(&(now.as_secs() as u32).to_le_bytes());
+        record.extend_from_slice(&now.subsec_micros().to_le_bytes());
+        record.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        record.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        record.extend_from_slice(packet);
+        self.output.write_all(&record)?;
+        self.output.flush()
+    }
+}
+
+// Replays the packets of one capture and records what is written into
+// another. The device is readable while packets are left, and always
+// writable; once the capture is exhausted it simply stays quiet.
+#[cfg(feature = "mio-wrapper")]
+pub struct PcapDevice {
+    reader: PcapReader<io::BufReader<fs::File>>,
+    writer: PcapWriter<fs::File>,
+    pending: Option<io::Result<Vec<u8>>>,
+    registration: Registration,
+    readiness: SetReadiness,
+}
+
+#[cfg(feature = "mio-wrapper")]
+impl PcapDevice {
+
+    pub fn new<P: AsRef<Path>, Q: AsRef<Path>>(input: P, output: Q) -> io::Result<Self> {
+        let reader = PcapReader::new(io::BufReader::new(fs::File::open(input)?))?;
+        let writer = PcapWriter::new(fs::File::create(output)?, reader.get_mode())?;
+        let (registration, readiness) = Registration::new2();
+
+        let mut device = PcapDevice {
+            reader,
+            writer,
+            pending: None,
+            registration,
+            readiness,
+        };
+        device.prefetch()?;
+        Ok(device)
+    }
+
+    // A read error is kept and handed out by the next `read_packet`.
+    fn prefetch(&mut self) -> io::Result<()> {
+        self.pending = self.reader.next_packet().transpose();
+        let ready = if self.pending.is_some() {
+            Ready::readable() | Ready::writable()
+        } else {
+            Ready::writable()
+        };
+        self.readiness.set_readiness(ready)
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.pending.is_none()
+    }
+}
+
+#[cfg(feature = "mio-wrapper")]
+impl Evented for PcapDevice {
+
+    fn register(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt)
+        -> io::Result<()> {
+        self.registration.register(poll, token, interest, opts)
+    }
+
+    fn reregister(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt)
+        -> io::Result<()> {
+        self.registration.reregister(poll, token, interest, opts)
+    }
+
+    fn deregister(&self, poll: &Poll) -> io::Result<()> {
+        #[allow(deprecated)]
+        self.registration.deregister(poll)
+    }
+}
+
+#[cfg(feature = "mio-wrapper")]
+impl Device for PcapDevice {
+    fn get_mode(&self) -> Mode {
+        self.reader.get_mode()
+    }
+
+    fn get_mtu(&self) -> io::Result<usize> {
+        Ok(MAX_PACKET_SIZE)
+    }
+
+    fn get_buffer_size(&self) -> usize {
+        match self.get_mode() {
+            Mode::Tun => MAX_PACKET_SIZE,
+            Mode::Tap => MAX_PACKET_SIZE + ETHERNET_OVERHEAD,
+        }
+    }
+
+    fn read_packet(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let packet = match self.pending.take() {
+            Some(packet) => packet,
+            None => return Err(io::ErrorKind::WouldBlock.into()),
+        };
+        self.prefetch()?;
+
+        let packet = packet?;
+        if packet.len() > buf.len() {
+            return Err(invalid(format!("packet of {} bytes does not fit into {}", packet.len(), buf.len())));
+        }
+        buf[..packet.len()].clone_from_slice(&packet);
+        Ok(packet.len())
+    }
+
+    fn write_packet(&mut self, packet: &[u8]) -> io::Result<usize> {
+        self.writer.write_packet(packet)?;
+        Ok(packet.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::Cursor;
+
+    use driver::Mode;
+    use super::*;
+
+    fn put16(buf: &mut Vec<u8>, value: u16, big_endian: bool) {
+        buf.extend_from_slice(&if big_endian { value.to_be_bytes() } else { value.to_le_bytes() });
+    }
+
+    fn put32(buf: &mut Vec<u8>, value: u32, big_endian: bool) {
+        buf.extend_from_slice(&if big_endian { value.to_be_bytes() } else { value.to_le_bytes() });
+    }
+
+    fn classic(big_endian: bool, linktype: u32, packets: &[&[u8]]) -> Vec<u8> {
+        let mut file = Vec::new();
+        put32(&mut file, PCAP_MAGIC, big_endian);
+        put16(&mut file, 2, big_endian);
+        put16(&mut file, 4, big_endian);
+        file.extend_from_slice(&[0u8; 8]);
+        put32(&mut file, 65535, big_endian);
+        put32(&mut file, linktype, big_endian);
+        for packet in packets {
+            file.extend_from_slice(&[0u8; 8]);
+            put32(&mut file, packet.len() as u32, big_endian);
+            put32(&mut file, packet.len() as u32, big_endian);
+            file.extend_from_slice(packet);
+        }
+        file
+    }
+
+    fn block(file: &mut Vec<u8>, ty: u32, body: &[u8], big_endian: bool) {
+        let padded = body.len().div_ceil(4) * 4;
+        put32(file, ty, big_endian);
+        put32(file, (12 + padded) as u32, big_endian);
+        file.extend_from_slice(body);
+        file.resize(file.len() + padded - body.len(), 0);
+        put32(file, (12 + padded) as u32, big_endian);
+    }
+
+    fn pcapng(big_endian: bool, linktype: u16, packets: &[&[u8]]) -> Vec<u8> {
+        let mut file = Vec::new();
+        let mut body = Vec::new();
+        put32(&mut body, PCAPNG_BYTE_ORDER_MAGIC, big_endian);
+        put16(&mut body, 1, big_endian);
+        put16(&mut body, 0, big_endian);
+        body.extend_from_slice(&[0xff; 8]);
+        block(&mut file, PCAPNG_SECTION_HEADER, &body, big_endian);
+
+        let mut body = Vec::new();
+        put16(&mut body, linktype, big_endian);
+        put16(&mut body, 0, big_endian);
+        put32(&mut body, 0, big_endian);
+        block(&mut file, PCAPNG_INTERFACE_DESCRIPTION, &body, big_endian);
+        // Statistics, to be skipped.
+        block(&mut file, 5, &[0u8; 12], big_endian);
+
+        for packet in packets {
+            let mut body = vec![0u8; 12];
+            put32(&mut body, packet.len() as u32, big_endian);
+            put32(&mut body, packet.len() as u32, big_endian);
+            body.extend_from_slice(packet);
+            block(&mut file, PCAPNG_ENHANCED_PACKET, &body, big_endian);
+        }
+        file
+    }
+
+    fn read_all(file: Vec<u8>) -> io::Result<(Mode, Vec<Vec<u8>>)> {
+        let mut reader = PcapReader::new(Cursor::new(file))?;
+        let mut packets = Vec::new();
+        while let Some(packet) = reader.next_packet()? {
+            packets.push(packet);
+        }
+        Ok((reader.get_mode(), packets))
+    }
+
+    const IP: &[u8] = &[0x45, 0, 0, 20, 0, 0, 0, 0, 64, 17, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2];
+    const FRAME: &[u8] = &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 2, 0, 0, 0, 0, 1, 0x08, 0x06, 0, 1, 8];
+
+    #[test]
+    fn reads_classic() {
+        for &big_endian in &[false, true] {
+            let (mode, packets) = read_all(classic(big_endian, LINKTYPE_RAW, &[IP, &IP[..3]])).unwrap();
+            assert_eq!((mode, packets), (Mode::Tun, vec![IP.to_vec(), IP[..3].to_vec()]));
+
+            let (mode, packets) = read_all(classic(big_endian, LINKTYPE_ETHERNET, &[FRAME])).unwrap();
+            assert_eq!((mode, packets), (Mode::Tap, vec![FRAME.to_vec()]));
+        }
+    }
+
+    #[test]
+    fn reads_pcapng() {
+        for &big_endian in &[false, true] {
+            let (mode, packets) = read_all(pcapng(big_endian, LINKTYPE_IPV4 as u16, &[IP, &IP[..3]])).unwrap();
+            assert_eq!((mode, packets), (Mode::Tun, vec![IP.to_vec(), IP[..3].to_vec()]));
+
+            let (mode, packets) = read_all(pcapng(big_endian, LINKTYPE_ETHERNET as u16, &[FRAME, FRAME])).unwrap();
+            assert_eq!((mode, packets), (Mode::Tap, vec![FRAME.to_vec(), FRAME.to_vec()]));
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_link_types() {
+        let err = read_all(classic(false, 147, &[])).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+        let err = read_all(pcapng(true, 147, &[])).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        // Cut anywhere but after the file header or the blocks before the
+        // packet, which leaves a valid empty capture.
+        let files = [(classic(false, LINKTYPE_RAW, &[IP]), vec![24]),
+                     (pcapng(true, LINKTYPE_RAW as u16, &[IP]), vec![48, 72])];
+        for (file, boundaries) in &files {
+            for len in (1..file.len()).filter(|len| !boundaries.contains(len)) {
+                let err = match read_all(file[..len].to_vec()) {
+                    Ok((_, packets)) => panic!("{} of {} bytes read as {:?}", len, file.len(), packets),
+                    Err(err) => err,
+                };
+                assert!(err.kind() == io::ErrorKind::UnexpectedEof || err.kind() == io::ErrorKind::InvalidData,
+                        "{} of {} bytes: {}", len, file.len(), err);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_huge_lengths() {
+        let mut file = classic(false, LINKTYPE_RAW, &[IP]);
+        file[32..36].clone_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(read_all(file).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let mut file = pcapng(false, LINKTYPE_RAW as u16, &[IP]);
+        let packet = file.len() - 52;
+        file[packet + 4..packet + 8].clone_from_slice(&0xffff_fff0u32.to_le_bytes());
+        assert_eq!(read_all(file).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let mut file = pcapng(true, LINKTYPE_RAW as u16, &[]);
+        file[4..8].clone_from_slice(&0xffff_fff0u32.to_be_bytes());
+        assert_eq!(read_all(file).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
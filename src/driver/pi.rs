@@ -0,0 +1,101 @@
+use driver::Mode;
+use packet::{EthernetPacket, ETHERTYPE_IPV4, ETHERTYPE_IPV6};
+
+/*
+    struct tun_pi, prepended to every packet unless IFF_NO_PI is set:
+         0                    15 16                  31
+        +----------------------+----------------------+
+        |        Flags         |    Proto (EtherType) |
+        +----------------------+----------------------+
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketInfo {
+    pub flags: u16,
+    pub proto: u16,
+}
+
+impl PacketInfo {
+    pub const LEN: usize = 4;
+
+    // Set by the kernel when the packet did not fit into the read buffer.
+    pub const TUN_PKT_STRIP: u16 = 0x0001;
+
+    pub fn new(proto: u16) -> Self {
+        PacketInfo { flags: 0, proto }
+    }
+
+    // `flags` is in host byte order, `proto` in network byte order.
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() < Self::LEN {
+            return None;
+        }
+
+        Some(PacketInfo {
+            flags: u16::from_ne_bytes([buf[0], buf[1]]),
+            proto: u16::from_be_bytes([buf[2], buf[3]]),
+        })
+    }
+
+    pub fn write(&self, buf: &mut [u8]) {
+        buf[0..2].clone_from_slice(&self.flags.to_ne_bytes());
+        buf[2..4].clone_from_slice(&self.proto.to_be_bytes());
+    }
+
+    pub fn to_bytes(&self) -> [u8; 4] {
+        let mut buf = [0u8; Self::LEN];
+        self.write(&mut buf);
+        buf
+    }
+
+    // Builds the header for an outgoing packet: TUN packets are classified by
+    // their IP version, TAP frames carry their EtherType.
+    pub fn for_packet(mode: Mode, packet: &[u8]) -> Option<Self> {
+        match mode {
+            Mode::Tun => match packet.first().map(|b| b >> 4) {
+                Some(4) => Some(PacketInfo::new(ETHERTYPE_IPV4)),
+                Some(6) => Some(PacketInfo::new(ETHERTYPE_IPV6)),
+                _ => None,
+            },
+            Mode::Tap => EthernetPacket::new(packet).map(|frame| PacketInfo::new(frame.ethertype())),
+        }
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        self.flags & Self::TUN_PKT_STRIP != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use driver::Mode;
+    use packet::{ETHERTYPE_ARP, ETHERTYPE_IPV4, ETHERTYPE_IPV6};
+    use super::PacketInfo;
+
+    #[test]
+    fn parses_and_writes_headers() {
+        let info = PacketInfo { flags: PacketInfo::TUN_PKT_STRIP, proto: ETHERTYPE_IPV6 };
+        let bytes = info.to_bytes();
+        assert_eq!(&bytes[2..], &[0x86, 0xdd]);
+        assert_eq!(&bytes[..2], &PacketInfo::TUN_PKT_STRIP.to_ne_bytes());
+        assert_eq!(PacketInfo::parse(&bytes), Some(info));
+        assert!(info.is_truncated());
+        assert!(!PacketInfo::new(ETHERTYPE_IPV4).is_truncated());
+        assert_eq!(PacketInfo::parse(&bytes[..3]), None);
+    }
+
+    #[test]
+    fn classifies_tun_packets_by_version() {
+        assert_eq!(PacketInfo::for_packet(Mode::Tun, &[0x45, 0]), Some(PacketInfo::new(ETHERTYPE_IPV4)));
+        assert_eq!(PacketInfo::for_packet(Mode::Tun, &[0x60, 0]), Some(PacketInfo::new(ETHERTYPE_IPV6)));
+        assert_eq!(PacketInfo::for_packet(Mode::Tun, &[0x50, 0]), None);
+        assert_eq!(PacketInfo::for_packet(Mode::Tun, &[]), None);
+    }
+
+    #[test]
+    fn classifies_tap_frames_by_ethertype() {
+        let mut frame = [0u8; 14];
+        frame[12..].clone_from_slice(&ETHERTYPE_ARP.to_be_bytes());
+        assert_eq!(PacketInfo::for_packet(Mode::Tap, &frame), Some(PacketInfo::new(ETHERTYPE_ARP)));
+        assert_eq!(PacketInfo::for_packet(Mode::Tap, &frame[..13]), None);
+    }
+}
@@ -0,0 +1,112 @@
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, Weak};
+
+use driver::PacketInfo;
+
+struct Shared {
+    free: Mutex<Vec<Vec<u8>>>,
+    buffer_size: usize,
+    capacity: usize,
+}
+
+// Hands out fixed-size packet buffers and takes them back when they are
+// dropped, so that steady-state forwarding does not allocate. At most
+// `capacity` idle buffers are kept, more are allocated on demand.
+#[derive(Clone)]
+pub struct BufferPool {
+    shared: Arc<Shared>,
+}
+
+impl BufferPool {
+
+    pub fn new(buffer_size: usize, capacity: usize) -> Self {
+        BufferPool {
+            shared: Arc::new(Shared {
+                free: Mutex::new(Vec::with_capacity(capacity)),
+                buffer_size,
+                capacity,
+            }),
+        }
+    }
+
+    // An empty buffer; write into `as_mut_buf` and then `set_len`.
+    pub fn get(&self) -> PacketBuf {
+        let data = self.shared.free.lock().unwrap().pop()
+            .unwrap_or_else(|| vec![0u8; self.shared.buffer_size]);
+        PacketBuf {
+            data,
+            len: 0,
+            info: None,
+            pool: Arc::downgrade(&self.shared),
+        }
+    }
+
+    pub fn get_buffer_size(&self) -> usize {
+        self.shared.buffer_size
+    }
+
+    pub fn get_idle(&self) -> usize {
+        self.shared.free.lock().unwrap().len()
+    }
+}
+
+// A packet in a pooled buffer. It derefs to the packet, while `as_mut_buf`
+// gives the whole buffer to read into. It can be sent to another thread and
+// returns to its pool from there. `Device::read_batch` keeps the stripped
+// `PacketInfo` of the packet with it.
+pub struct PacketBuf {
+    data: Vec<u8>,
+    len: usize,
+    info: Option<PacketInfo>,
+    pool: Weak<Shared>,
+}
+
+impl PacketBuf {
+
+    pub fn as_mut_buf(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn set_len(&mut self, len: usize) {
+        assert!(len <= self.data.len(), "packet length {} exceeds buffer size {}", len, self.data.len());
+        self.len = len;
+    }
+
+    pub fn get_packet_info(&self) -> Option<PacketInfo> {
+        self.info
+    }
+
+    pub fn set_packet_info(&mut self, info: Option<PacketInfo>) {
+        self.info = info;
+    }
+}
+
+impl Deref for PacketBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+impl DerefMut for PacketBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.data[..self.len]
+    }
+}
+
+impl Drop for PacketBuf {
+    fn drop(&mut self) {
+        if let Some(shared) = self.pool.upgrade() {
+            let mut free = shared.free.lock().unwrap();
+            if free.len() < shared.capacity {
+                free.push(mem::take(&mut self.data));
+            }
+        }
+    }
+}
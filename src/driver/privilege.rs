@@ -0,0 +1,156 @@
+/*
+ * Reference:
+ *  https://man7.org/linux/man-pages/man7/capabilities.7.html
+ *  https://man7.org/linux/man-pages/man2/capset.2.html
+ *
+ * Date: Oct 18 CST 2026
+ */
+
+use std::{fs, io, ptr};
+
+use libc;
+use libc::{c_int, c_ulong, gid_t, uid_t};
+
+use driver::{cvt, Error, Result};
+
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x20080522;
+
+#[repr(C)]
+struct CapHeader {
+    version: u32,
+    pid: c_int,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+struct CapData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+// Capabilities that may be kept across `PrivilegeDrop::apply`.
+bitflags! {
+    pub struct Capabilities: u64 {
+        // `Iface::down` and the rest of the interface configuration, also
+        // inside a `Netns`, whose sockets are opened by `Iface::up`.
+        const NET_ADMIN = 1 << 12;
+    }
+}
+
+// The effective and permitted sets of this thread.
+fn capget() -> io::Result<(u64, u64)> {
+    let mut header = CapHeader { version: LINUX_CAPABILITY_VERSION_3, pid: 0 };
+    let mut data = [CapData::default(); 2];
+    cvt(unsafe { libc::syscall(libc::SYS_capget, &mut header, data.as_mut_ptr()) } as c_int)?;
+    Ok((data[0].effective as u64 | (data[1].effective as u64) << 32,
+        data[0].permitted as u64 | (data[1].permitted as u64) << 32))
+}
+
+fn capset(caps: u64) -> io::Result<()> {
+    let mut header = CapHeader { version: LINUX_CAPABILITY_VERSION_3, pid: 0 };
+    let data = [
+        CapData { effective: caps as u32, permitted: caps as u32, inheritable: 0 },
+        CapData { effective: (caps >> 32) as u32, permitted: (caps >> 32) as u32, inheritable: 0 },
+    ];
+    cvt(unsafe { libc::syscall(libc::SYS_capset, &mut header, data.as_ptr()) } as c_int)?;
+    Ok(())
+}
+
+fn prctl(option: c_int, arg: c_ulong) -> io::Result<()> {
+    cvt(unsafe { libc::prctl(option, arg, 0 as c_ulong, 0 as c_ulong, 0 as c_ulong) })?;
+    Ok(())
+}
+
+fn lost(what: String) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::PermissionDenied, what))
+}
+
+// Switches a root process to an unprivileged uid and gid once the interface
+// is set up. Supplementary groups are cleared, and no capabilities survive
+// except those passed to `keep`, which are also all that remain in the
+// bounding set. Every step is checked and the result verified, so that an
+// incomplete drop is an error rather than a silently privileged process.
+//
+// Capabilities belong to threads: apply this before spawning any, as the
+// uid and gid change for the whole process but other threads would keep
+// their capabilities.
+#[derive(Debug, Clone)]
+pub struct PrivilegeDrop {
+    uid: uid_t,
+    gid: gid_t,
+    keep: Capabilities,
+}
+
+impl PrivilegeDrop {
+
+    pub fn new(uid: uid_t, gid: gid_t) -> Self {
+        PrivilegeDrop {
+            uid,
+            gid,
+            keep: Capabilities::empty(),
+        }
+    }
+
+    pub fn keep(mut self, capabilities: Capabilities) -> Self {
+        self.keep = capabilities;
+        self
+    }
+
+    pub fn apply(&self) -> Result<()> {
+        let keep = self.keep.bits;
+
+        // Needs CAP_SETPCAP, so before the uid changes. Capabilities the
+        // kernel does not know of end the loop with EINVAL.
+        let last = fs::read_to_string("/proc/sys/kernel/cap_last_cap").ok()
+            .and_then(|last| last.trim().parse::<u32>().ok())
+            .unwrap_or(63);
+        for cap in (0..=last).filter(|cap| keep & (1 << cap) == 0) {
+            match prctl(libc::PR_CAPBSET_DROP, cap as c_ulong) {
+                Ok(()) => (),
+                Err(ref e) if e.raw_os_error() == Some(libc::EINVAL) => break,
+                Err(e) => return Err(Error::system("drop bounding capabilities", e)),
+            }
+        }
+        prctl(libc::PR_CAP_AMBIENT, libc::PR_CAP_AMBIENT_CLEAR_ALL as c_ulong)
+            .map_err(|e| Error::system("clear ambient capabilities", e))?;
+
+        // Otherwise setresuid clears the permitted set along with the uid.
+        prctl(libc::PR_SET_KEEPCAPS, (keep != 0) as c_ulong)
+            .map_err(|e| Error::system("keep capabilities", e))?;
+        cvt(unsafe { libc::setgroups(0, ptr::null()) })
+            .map_err(|e| Error::system("setgroups", e))?;
+        cvt(unsafe { libc::setresgid(self.gid, self.gid, self.gid) })
+            .map_err(|e| Error::system("setresgid", e))?;
+        cvt(unsafe { libc::setresuid(self.uid, self.uid, self.uid) })
+            .map_err(|e| Error::system("setresuid", e))?;
+        prctl(libc::PR_SET_KEEPCAPS, 0)
+            .map_err(|e| Error::system("keep capabilities", e))?;
+        capset(keep).map_err(|e| Error::system("capset", e))?;
+
+        self.verify()
+    }
+
+    fn verify(&self) -> Result<()> {
+        let (mut ruid, mut euid, mut suid) = (0, 0, 0);
+        let (mut rgid, mut egid, mut sgid) = (0, 0, 0);
+        cvt(unsafe { libc::getresuid(&mut ruid, &mut euid, &mut suid) })?;
+        cvt(unsafe { libc::getresgid(&mut rgid, &mut egid, &mut sgid) })?;
+        if [ruid, euid, suid].iter().any(|&uid| uid != self.uid)
+            || [rgid, egid, sgid].iter().any(|&gid| gid != self.gid) {
+            return Err(lost(format!("uids {:?} and gids {:?} after dropping to {}:{}",
+                                    (ruid, euid, suid), (rgid, egid, sgid), self.uid, self.gid)));
+        }
+
+        let (effective, permitted) = capget()?;
+        if effective != self.keep.bits || permitted != self.keep.bits {
+            return Err(lost(format!("capabilities {:#x}/{:#x} remain, expected {:#x}",
+                                    effective, permitted, self.keep.bits)));
+        }
+
+        if self.uid != 0 && unsafe { libc::setuid(0) } == 0 {
+            return Err(lost("root could be regained after dropping privileges".to_string()));
+        }
+        Ok(())
+    }
+}
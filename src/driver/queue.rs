@@ -0,0 +1,169 @@
+use std::collections::VecDeque;
+use std::io;
+use std::ops::Deref;
+
+use driver::Device;
+
+// What `TxQueue::push` does when the queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropPolicy {
+    // Drop the packet being pushed.
+    TailDrop,
+    // Drop the oldest queued packet to make room.
+    HeadDrop,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TxStats {
+    pub sent: u64,
+    pub dropped: u64,
+    pub errors: u64,
+}
+
+// Packets waiting for a non-blocking device to accept them. Push everything
+// to be sent, then `flush`; while packets remain queued, register the device
+// for writable as well and flush again on every writable event.
+pub struct TxQueue<P> {
+    packets: VecDeque<P>,
+    capacity: usize,
+    policy: DropPolicy,
+    stats: TxStats,
+}
+
+impl<P: Deref<Target = [u8]>> TxQueue<P> {
+
+    pub fn new(capacity: usize, policy: DropPolicy) -> Self {
+        assert!(capacity > 0, "queue capacity must not be zero");
+        TxQueue {
+            packets: VecDeque::with_capacity(capacity),
+            capacity,
+            policy,
+            stats: TxStats::default(),
+        }
+    }
+
+    // Returns false if a packet was dropped.
+    pub fn push(&mut self, packet: P) -> bool {
+        if self.packets.len() < self.capacity {
+            self.packets.push_back(packet);
+            return true;
+        }
+
+        self.stats.dropped += 1;
+        if self.policy == DropPolicy::HeadDrop {
+            self.packets.pop_front();
+            self.packets.push_back(packet);
+        }
+        false
+    }
+
+    // Writes queued packets until the device would block. A packet the device
+    // refuses for another reason is dropped and counted as an error, and the
+    // error returned; the rest stay queued.
+    pub fn flush<D: Device>(&mut self, device: &mut D) -> io::Result<()> {
+        while let Some(packet) = self.packets.pop_front() {
+            match device.write_packet(&packet) {
+                Ok(_) => self.stats.sent += 1,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    self.packets.push_front(packet);
+                    break;
+                },
+                Err(e) => {
+                    self.stats.errors += 1;
+                    return Err(e);
+                },
+            }
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.packets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+
+    pub fn get_capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn get_policy(&self) -> DropPolicy {
+        self.policy
+    }
+
+    pub fn get_stats(&self) -> TxStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use driver::{Device, MemoryDevice, Mode};
+    use super::{DropPolicy, TxQueue};
+
+    fn drain(device: &mut MemoryDevice) -> Vec<u8> {
+        let mut buf = [0u8; 64];
+        let mut first_bytes = Vec::new();
+        while let Ok(len) = device.read_packet(&mut buf) {
+            assert_eq!(len, 8);
+            first_bytes.push(buf[0]);
+        }
+        first_bytes
+    }
+
+    fn fill(policy: DropPolicy) -> (TxQueue<Vec<u8>>, MemoryDevice, MemoryDevice) {
+        let (a, b) = MemoryDevice::pair(Mode::Tun, 1500).unwrap();
+        let mut queue = TxQueue::new(3, policy);
+        for i in 0..5u8 {
+            assert_eq!(queue.push(vec![i; 8]), i < 3);
+        }
+        (queue, a, b)
+    }
+
+    #[test]
+    fn tail_drop_keeps_oldest() {
+        let (mut queue, mut a, mut b) = fill(DropPolicy::TailDrop);
+        queue.flush(&mut a).unwrap();
+        assert!(queue.is_empty());
+        assert_eq!(drain(&mut b), vec![0, 1, 2]);
+        assert_eq!((queue.get_stats().sent, queue.get_stats().dropped), (3, 2));
+    }
+
+    #[test]
+    fn head_drop_keeps_newest() {
+        let (mut queue, mut a, mut b) = fill(DropPolicy::HeadDrop);
+        queue.flush(&mut a).unwrap();
+        assert_eq!(drain(&mut b), vec![2, 3, 4]);
+        assert_eq!((queue.get_stats().sent, queue.get_stats().dropped), (3, 2));
+    }
+
+    #[test]
+    fn keeps_packets_while_device_blocks() {
+        let (mut a, mut b) = MemoryDevice::pair(Mode::Tun, 1500).unwrap();
+        let mut queue = TxQueue::new(4096, DropPolicy::TailDrop);
+        for i in 0..4096 {
+            queue.push(vec![i as u8; 8]);
+        }
+        queue.flush(&mut a).unwrap();
+        let queued = queue.len();
+        assert!(queued > 0, "socket buffer never filled");
+
+        let delivered = drain(&mut b).len();
+        assert_eq!(delivered + queued, 4096);
+        queue.flush(&mut a).unwrap();
+        assert!(queue.len() < queued);
+    }
+
+    #[test]
+    fn counts_refused_packets() {
+        let (mut a, _b) = MemoryDevice::pair(Mode::Tun, 16).unwrap();
+        let mut queue = TxQueue::new(4, DropPolicy::TailDrop);
+        queue.push(vec![0; 64]);
+        queue.push(vec![1; 8]);
+        assert!(queue.flush(&mut a).is_err());
+        queue.flush(&mut a).unwrap();
+        assert_eq!(queue.get_stats(), super::TxStats { sent: 1, dropped: 0, errors: 1 });
+    }
+}
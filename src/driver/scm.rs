@@ -0,0 +1,121 @@
+use std::{io, mem, ptr};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+
+use libc;
+use libc::c_int;
+
+use driver::cvt;
+
+// Passes `fd` to the peer of `socket` as SCM_RIGHTS ancillary data, next to a
+// single dummy byte since a message cannot be empty.
+pub fn send_fd(socket: &UnixStream, fd: RawFd) -> io::Result<()> {
+    let mut byte = [0u8; 1];
+    let mut iov = libc::iovec { iov_base: byte.as_mut_ptr() as *mut _, iov_len: byte.len() };
+    let mut control = [0u8; 64];
+    let space = unsafe { libc::CMSG_SPACE(mem::size_of::<c_int>() as u32) } as usize;
+
+    unsafe {
+        let mut msg: libc::msghdr = mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut _;
+        msg.msg_controllen = space as _;
+
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<c_int>() as u32) as _;
+        ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut c_int, fd);
+
+        cvt(libc::sendmsg(socket.as_raw_fd(), &msg, libc::MSG_NOSIGNAL))?;
+    }
+    Ok(())
+}
+
+// Receives one fd sent with `send_fd`. The fd is close-on-exec; anything but
+// exactly one fd in the message is an error, and extra fds are closed.
+pub fn recv_fd(socket: &UnixStream) -> io::Result<RawFd> {
+    let mut byte = [0u8; 1];
+    let mut iov = libc::iovec { iov_base: byte.as_mut_ptr() as *mut _, iov_len: byte.len() };
+    let mut control = [0u8; 64];
+
+    let mut fds = Vec::new();
+    let (len, flags) = unsafe {
+        let mut msg: libc::msghdr = mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut _;
+        msg.msg_controllen = control.len() as _;
+
+        let len = cvt(libc::recvmsg(socket.as_raw_fd(), &mut msg, libc::MSG_CMSG_CLOEXEC))?;
+
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let data = libc::CMSG_DATA(cmsg);
+                let count = ((*cmsg).cmsg_len as usize - (data as usize - cmsg as usize))
+                                / mem::size_of::<c_int>();
+                for i in 0..count {
+                    fds.push(ptr::read_unaligned((data as *const c_int).add(i)));
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+        (len, msg.msg_flags)
+    };
+
+    if fds.len() == 1 && flags & libc::MSG_CTRUNC == 0 {
+        return Ok(fds[0]);
+    }
+    for fd in fds {
+        unsafe { libc::close(fd) };
+    }
+    if len == 0 {
+        Err(io::Error::new(io::ErrorKind::UnexpectedEof, "socket closed before an fd was received"))
+    } else {
+        Err(io::Error::new(io::ErrorKind::InvalidData, "expected exactly one fd"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::{self, Read, Write};
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+    use std::os::unix::net::UnixStream;
+
+    use libc;
+
+    use super::{recv_fd, send_fd};
+
+    #[test]
+    fn passes_fds() {
+        let mut pipe = [0; 2];
+        assert_eq!(unsafe { libc::pipe2(pipe.as_mut_ptr(), libc::O_CLOEXEC) }, 0);
+        let mut reader = unsafe { fs::File::from_raw_fd(pipe[0]) };
+        let writer = unsafe { fs::File::from_raw_fd(pipe[1]) };
+
+        let (a, b) = UnixStream::pair().unwrap();
+        send_fd(&a, writer.as_raw_fd()).unwrap();
+        drop(writer);
+        let mut received = unsafe { fs::File::from_raw_fd(recv_fd(&b).unwrap()) };
+        let flags = unsafe { libc::fcntl(received.as_raw_fd(), libc::F_GETFD) };
+        assert_eq!(flags & libc::FD_CLOEXEC, libc::FD_CLOEXEC);
+
+        received.write_all(b"lsoup").unwrap();
+        drop(received);
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"lsoup");
+    }
+
+    #[test]
+    fn refuses_messages_without_fds() {
+        let (mut a, b) = UnixStream::pair().unwrap();
+        a.write_all(b"x").unwrap();
+        assert_eq!(recv_fd(&b).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        drop(a);
+        assert_eq!(recv_fd(&b).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
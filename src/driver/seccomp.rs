@@ -0,0 +1,378 @@
+/*
+ * Reference:
+ *  https://www.kernel.org/doc/Documentation/prctl/seccomp_filter.txt
+ *  https://man7.org/linux/man-pages/man2/seccomp.2.html
+ *
+ * Date: Oct 18 CST 2026
+ */
+
+use std::io;
+
+use libc;
+use libc::{c_long, c_ulong};
+
+use driver::bpf::{SockFilter, SockFprog};
+use driver::{cvt, Error, Result};
+
+const SECCOMP_SET_MODE_FILTER: c_ulong = 1;
+const SECCOMP_FILTER_FLAG_TSYNC: c_ulong = 1;
+
+const SECCOMP_RET_KILL_PROCESS: u32 = 0x8000_0000;
+const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+const SECCOMP_RET_LOG: u32 = 0x7ffc_0000;
+const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+
+// Offsets in struct seccomp_data. Arguments are 64 bits wide, the low half
+// first on the little-endian architectures below.
+const SECCOMP_DATA_NR: u32 = 0;
+const SECCOMP_DATA_ARCH: u32 = 4;
+const SECCOMP_DATA_ARG0: u32 = 16;
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+// x32 system calls pass as x86_64 ones, with this bit set in the number.
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+compile_error!("seccomp filters are only built for x86_64 and aarch64");
+
+// BPF_LD | BPF_W | BPF_ABS, BPF_JMP | BPF_JEQ | BPF_K, BPF_JMP | BPF_JSET | BPF_K
+// and BPF_RET | BPF_K.
+const LD_W_ABS: u16 = 0x20;
+const JEQ: u16 = 0x15;
+const JSET: u16 = 0x45;
+const RET: u16 = 0x06;
+
+// What the forwarding loop does once set up: packets through read and write,
+// mio's epoll and wakeups, which workers create as they start, allocation,
+// threads parking and exiting, and `Iface::down` on drop, which talks
+// netlink and rewrites a sysctl through fds `Iface::up` opened. Starting
+// threads is allowed by the filter itself, see `Seccomp::program`.
+#[cfg(target_arch = "x86_64")]
+const FORWARDING: &[c_long] = &[
+    libc::SYS_epoll_wait,
+    libc::SYS_poll,
+];
+#[cfg(target_arch = "aarch64")]
+const FORWARDING: &[c_long] = &[];
+
+const FORWARDING_COMMON: &[c_long] = &[
+    libc::SYS_read,
+    libc::SYS_write,
+    libc::SYS_readv,
+    libc::SYS_writev,
+    libc::SYS_recvfrom,
+    libc::SYS_sendto,
+    libc::SYS_recvmsg,
+    libc::SYS_sendmsg,
+    libc::SYS_epoll_create1,
+    libc::SYS_epoll_pwait,
+    libc::SYS_epoll_ctl,
+    libc::SYS_pipe2,
+    libc::SYS_fcntl,
+    libc::SYS_futex,
+    libc::SYS_sched_yield,
+    libc::SYS_nanosleep,
+    libc::SYS_clock_nanosleep,
+    libc::SYS_clock_gettime,
+    libc::SYS_mmap,
+    libc::SYS_munmap,
+    libc::SYS_mremap,
+    libc::SYS_mprotect,
+    libc::SYS_madvise,
+    libc::SYS_brk,
+    libc::SYS_rt_sigaction,
+    libc::SYS_rt_sigprocmask,
+    libc::SYS_rt_sigreturn,
+    libc::SYS_sigaltstack,
+    libc::SYS_gettid,
+    libc::SYS_sched_getaffinity,
+    libc::SYS_close,
+    libc::SYS_exit,
+    libc::SYS_exit_group,
+    libc::SYS_set_robust_list,
+    libc::SYS_rseq,
+    // Iface::down
+    libc::SYS_pwrite64,
+];
+
+// Once installed, a system call outside the allowlist kills the whole
+// process with SIGSYS, so that a bug in packet parsing cannot be turned
+// into anything the forwarding loop would not do anyway. The filter covers
+// every thread and cannot be removed; it also sets no_new_privs. A killed
+// process does not run `Iface::down`, so its routing rules stay behind.
+#[derive(Debug, Clone)]
+pub struct Seccomp {
+    syscalls: Vec<c_long>,
+    log_only: bool,
+}
+
+impl Default for Seccomp {
+    fn default() -> Self {
+        Seccomp::new()
+    }
+}
+
+impl Seccomp {
+
+    // The allowlist of the forwarding loop of lsoup and the devices of this
+    // crate. Backends with other needs, e.g. UringDevice with
+    // io_uring_enter, extend it with `allow`.
+    pub fn new() -> Self {
+        Seccomp {
+            syscalls: FORWARDING_COMMON.iter().chain(FORWARDING).cloned().collect(),
+            log_only: false,
+        }
+    }
+
+    // An empty allowlist, to be filled with `allow`.
+    pub fn empty() -> Self {
+        Seccomp {
+            syscalls: Vec::new(),
+            log_only: false,
+        }
+    }
+
+    pub fn allow(mut self, syscall: c_long) -> Self {
+        if !self.syscalls.contains(&syscall) {
+            self.syscalls.push(syscall);
+        }
+        self
+    }
+
+    // Logs forbidden system calls to the kernel log instead of killing, to
+    // find out what an allowlist lacks.
+    pub fn log_only(mut self, log_only: bool) -> Self {
+        self.log_only = log_only;
+        self
+    }
+
+    /*
+        Threads may always start, but clone is refused anything that is not
+        a thread, such as a new process or a new namespace. clone3 passes its
+        flags in memory the filter cannot read, so it fails with ENOSYS and
+        the C library falls back to clone. Opening files fails with EACCES
+        rather than killing, as glibc may try once a new thread allocates,
+        to count CPUs, and copes with the failure.
+
+        ld  [arch]
+        jeq #AUDIT_ARCH, 1, 0      ; other ABIs, such as i386, are refused
+        ret #KILL
+        ld  [nr]
+        jset #X32_SYSCALL_BIT, 0, 1 ; and so is x32, which shares AUDIT_ARCH
+        ret #KILL
+        jeq #syscall_0, N+7, 0     ; to the final allow
+        ...
+        jeq #syscall_N-1, 8, 0
+        jeq #clone3, 0, 1
+        ret #ERRNO(ENOSYS)
+        jeq #openat, 0, 1
+        ret #ERRNO(EACCES)
+        jeq #clone, 0, 2
+        ld  [args[0]]              ; the low half of flags
+        jset #CLONE_THREAD, 1, 0
+        ret #KILL
+        ret #ALLOW
+    */
+    fn program(&self) -> Result<Vec<SockFilter>> {
+        let deny = if self.log_only { SECCOMP_RET_LOG } else { SECCOMP_RET_KILL_PROCESS };
+        let insn = |code, jt, jf, k| SockFilter { code, jt, jf, k };
+        let tail = [
+            insn(JEQ, 0, 1, libc::SYS_clone3 as u32),
+            insn(RET, 0, 0, SECCOMP_RET_ERRNO | libc::ENOSYS as u32),
+            insn(JEQ, 0, 1, libc::SYS_openat as u32),
+            insn(RET, 0, 0, SECCOMP_RET_ERRNO | libc::EACCES as u32),
+            insn(JEQ, 0, 2, libc::SYS_clone as u32),
+            insn(LD_W_ABS, 0, 0, SECCOMP_DATA_ARG0),
+            insn(JSET, 1, 0, libc::CLONE_THREAD as u32),
+            insn(RET, 0, 0, deny),
+        ];
+
+        let count = self.syscalls.len();
+        if count + tail.len() > u8::MAX as usize + 1 {
+            return Err(Error::Io(io::Error::new(io::ErrorKind::InvalidInput,
+                                                format!("{} system calls exceed the {} a filter jumps over",
+                                                        count, u8::MAX as usize + 1 - tail.len()))));
+        }
+
+        let mut program = vec![
+            insn(LD_W_ABS, 0, 0, SECCOMP_DATA_ARCH),
+            insn(JEQ, 1, 0, AUDIT_ARCH),
+            insn(RET, 0, 0, SECCOMP_RET_KILL_PROCESS),
+            insn(LD_W_ABS, 0, 0, SECCOMP_DATA_NR),
+            insn(JSET, 0, 1, X32_SYSCALL_BIT),
+            insn(RET, 0, 0, SECCOMP_RET_KILL_PROCESS),
+        ];
+        for (i, &syscall) in self.syscalls.iter().enumerate() {
+            program.push(insn(JEQ, (count - 1 - i + tail.len()) as u8, 0, syscall as u32));
+        }
+        program.extend_from_slice(&tail);
+        program.push(insn(RET, 0, 0, SECCOMP_RET_ALLOW));
+        Ok(program)
+    }
+
+    pub fn apply(&self) -> Result<()> {
+        let program = self.program()?;
+        let fprog = SockFprog {
+            len: program.len() as u16,
+            filter: program.as_ptr(),
+        };
+
+        // Lets an unprivileged process install the filter, and keeps exec
+        // from granting anything the filter could not account for.
+        cvt(unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1 as c_ulong, 0 as c_ulong, 0 as c_ulong, 0 as c_ulong) })
+            .map_err(|e| Error::system("set no_new_privs", e))?;
+        // With TSYNC, a positive result is the id of a thread that could not
+        // be synchronized.
+        let result = unsafe {
+            libc::syscall(libc::SYS_seccomp, SECCOMP_SET_MODE_FILTER, SECCOMP_FILTER_FLAG_TSYNC,
+                          &fprog as *const SockFprog)
+        };
+        match result {
+            0 => Ok(()),
+            -1 => Err(Error::system("seccomp", io::Error::last_os_error())),
+            tid => Err(Error::Io(io::Error::other(format!("seccomp filter not installed on thread {}", tid)))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use libc;
+
+    use super::Seccomp;
+
+    // Runs `f` in a forked child and returns its wait status.
+    fn in_child<F: FnOnce()>(f: F) -> libc::c_int {
+        match unsafe { libc::fork() } {
+            -1 => panic!("fork failed"),
+            0 => {
+                f();
+                unsafe { libc::_exit(0) };
+            },
+            pid => {
+                let mut status = 0;
+                assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+                status
+            },
+        }
+    }
+
+    // What lsoup does under the filter: a worker thread starts, polls its
+    // device and answers an echo request, then the thread exits. Files
+    // cannot be opened.
+    #[cfg(feature = "mio-wrapper")]
+    #[test]
+    fn forwards_under_filter() {
+        use std::{fs, io, thread};
+        use std::time::Duration;
+
+        use mio::{Events, Poll, PollOpt, Ready, Token};
+
+        use driver::{Device, MemoryDevice, Mode};
+        use packet::icmp::{echo_reply, ICMP_ECHO_REPLY, ICMP_ECHO_REQUEST};
+
+        fn wait_readable(device: &MemoryDevice) {
+            let poll = Poll::new().unwrap();
+            poll.register(device, Token(0), Ready::readable(), PollOpt::level()).unwrap();
+            let mut events = Events::with_capacity(1);
+            while events.is_empty() {
+                poll.poll(&mut events, Some(Duration::from_secs(5))).unwrap();
+            }
+        }
+
+        let mut request = vec![
+            0x45, 0, 0, 33, 0, 1, 0x40, 0, 64, 1, 0, 0,
+            172, 32, 0, 1, 172, 32, 0, 2,
+            ICMP_ECHO_REQUEST, 0, 0, 0, 0x12, 0x34, 0, 1,
+        ];
+        request.extend_from_slice(b"lsoup");
+
+        let status = in_child(move || {
+            let (mut host, mut responder) = MemoryDevice::pair(Mode::Tun, 1500).unwrap();
+            if Seccomp::new().apply().is_err() {
+                unsafe { libc::_exit(2) };
+            }
+
+            let worker = thread::spawn(move || {
+                wait_readable(&responder);
+                let mut buf = vec![0u8; responder.get_buffer_size()];
+                let len = responder.read_packet(&mut buf).unwrap();
+                assert!(echo_reply(&mut buf[..len]));
+                responder.write_packet(&buf[..len]).unwrap();
+            });
+            host.write_packet(&request).unwrap();
+            wait_readable(&host);
+            let mut buf = [0u8; 1500];
+            let len = host.read_packet(&mut buf).unwrap();
+            if worker.join().is_err() {
+                unsafe { libc::_exit(3) };
+            }
+            if len != request.len() || buf[20] != ICMP_ECHO_REPLY || buf[12..16] != request[16..20] {
+                unsafe { libc::_exit(4) };
+            }
+            match fs::File::open("/proc/self/status") {
+                Err(ref e) if e.kind() == io::ErrorKind::PermissionDenied => {},
+                _ => unsafe { libc::_exit(5) },
+            }
+        });
+        assert!(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0, "status {:#x}", status);
+    }
+
+    #[test]
+    fn new_process_kills_process() {
+        let status = in_child(|| {
+            if Seccomp::new().apply().is_err() {
+                unsafe { libc::_exit(2) };
+            }
+            if unsafe { libc::fork() } == 0 {
+                unsafe { libc::_exit(0) };
+            }
+            process::abort();
+        });
+        assert!(libc::WIFSIGNALED(status), "status {:#x}", status);
+        assert_eq!(libc::WTERMSIG(status), libc::SIGSYS);
+    }
+
+    #[test]
+    fn forbidden_syscall_kills_process() {
+        let status = in_child(|| {
+            if Seccomp::new().apply().is_err() {
+                unsafe { libc::_exit(2) };
+            }
+            unsafe { libc::syscall(libc::SYS_getppid) };
+            process::abort();
+        });
+        assert!(libc::WIFSIGNALED(status), "status {:#x}", status);
+        assert_eq!(libc::WTERMSIG(status), libc::SIGSYS);
+    }
+
+    // Whatever the allowlist: even in log-only mode, an x32 call is killed.
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn x32_syscall_kills_process() {
+        let status = in_child(|| {
+            if Seccomp::new().log_only(true).apply().is_err() {
+                unsafe { libc::_exit(2) };
+            }
+            unsafe { libc::syscall(libc::SYS_write | 0x4000_0000, libc::STDERR_FILENO, b"".as_ptr(), 0) };
+            process::abort();
+        });
+        assert!(libc::WIFSIGNALED(status), "status {:#x}", status);
+        assert_eq!(libc::WTERMSIG(status), libc::SIGSYS);
+    }
+
+    #[test]
+    fn log_only_does_not_kill() {
+        let status = in_child(|| {
+            if Seccomp::new().log_only(true).apply().is_err() {
+                unsafe { libc::_exit(2) };
+            }
+            unsafe { libc::syscall(libc::SYS_getppid) };
+        });
+        assert!(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0, "status {:#x}", status);
+    }
+}
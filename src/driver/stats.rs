@@ -0,0 +1,145 @@
+use std::io;
+
+use libc;
+
+use driver::netlink::{Link, Netlink};
+
+fn link(name: &str) -> io::Result<Link> {
+    Netlink::new()?.link(name)
+}
+
+// Interface counters as the kernel keeps them, the same as in
+// /sys/class/net/<name>/statistics but read over netlink, which also works
+// from inside another network namespace. `rx` is what the interface received,
+// i.e. what we wrote into a TUN/TAP device, `tx` what it handed to us: TUN
+// and TAP count a packet as sent once it was read, so packets still queued
+// show up in neither `tx_packets` nor `tx_dropped`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_errors: u64,
+    pub tx_errors: u64,
+    pub rx_dropped: u64,
+    pub tx_dropped: u64,
+}
+
+impl Stats {
+
+    pub fn for_interface(name: &str) -> io::Result<Self> {
+        Ok(Stats::from_link(&link(name)?))
+    }
+
+    pub(super) fn from_link(link: &Link) -> Self {
+        let stats = link.stats;
+        Stats {
+            rx_packets: stats[0],
+            tx_packets: stats[1],
+            rx_bytes: stats[2],
+            tx_bytes: stats[3],
+            rx_errors: stats[4],
+            tx_errors: stats[5],
+            rx_dropped: stats[6],
+            tx_dropped: stats[7],
+        }
+    }
+
+    // The counts since `earlier`, for rates between two samples.
+    pub fn since(&self, earlier: &Stats) -> Stats {
+        Stats {
+            rx_packets: self.rx_packets.saturating_sub(earlier.rx_packets),
+            tx_packets: self.tx_packets.saturating_sub(earlier.tx_packets),
+            rx_bytes: self.rx_bytes.saturating_sub(earlier.rx_bytes),
+            tx_bytes: self.tx_bytes.saturating_sub(earlier.tx_bytes),
+            rx_errors: self.rx_errors.saturating_sub(earlier.rx_errors),
+            tx_errors: self.tx_errors.saturating_sub(earlier.tx_errors),
+            rx_dropped: self.rx_dropped.saturating_sub(earlier.rx_dropped),
+            tx_dropped: self.tx_dropped.saturating_sub(earlier.tx_dropped),
+        }
+    }
+}
+
+// RFC 2863 operational state, as shown by `ip link`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperState {
+    Unknown,
+    NotPresent,
+    Down,
+    LowerLayerDown,
+    Testing,
+    Dormant,
+    Up,
+}
+
+impl OperState {
+    fn from_raw(value: u8) -> Self {
+        match value {
+            1 => OperState::NotPresent,
+            2 => OperState::Down,
+            3 => OperState::LowerLayerDown,
+            4 => OperState::Testing,
+            5 => OperState::Dormant,
+            6 => OperState::Up,
+            _ => OperState::Unknown,
+        }
+    }
+}
+
+// `admin_up` is what `ip link set up` controls, `carrier` whether the lower
+// layer is there: for TUN/TAP, an attached queue unless TUNSETCARRIER
+// turned it off. Only with both is the interface operationally up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LinkState {
+    pub admin_up: bool,
+    pub carrier: bool,
+    pub oper_state: OperState,
+}
+
+impl LinkState {
+
+    pub fn for_interface(name: &str) -> io::Result<Self> {
+        Ok(LinkState::from_link(&link(name)?))
+    }
+
+    pub(super) fn from_link(link: &Link) -> Self {
+        LinkState {
+            admin_up: link.flags & libc::IFF_UP as u32 != 0,
+            carrier: link.carrier,
+            oper_state: OperState::from_raw(link.oper_state),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::UdpSocket;
+
+    use super::{LinkState, OperState, Stats};
+
+    #[test]
+    fn reads_loopback() {
+        let before = Stats::for_interface("lo").unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.send_to(&[0u8; 100], socket.local_addr().unwrap()).unwrap();
+        let after = Stats::for_interface("lo").unwrap();
+
+        let delta = after.since(&before);
+        assert_eq!(delta.rx_packets, after.rx_packets - before.rx_packets);
+        assert_eq!(delta.tx_bytes, after.tx_bytes - before.tx_bytes);
+        assert!(delta.rx_packets >= 1 && delta.tx_packets >= 1);
+        assert!(delta.rx_bytes >= 128 && delta.tx_bytes >= 128);
+        assert_eq!(before.since(&after), Stats::default());
+
+        let state = LinkState::for_interface("lo").unwrap();
+        assert!(state.admin_up);
+        assert_eq!(state.oper_state, OperState::Unknown);
+    }
+
+    #[test]
+    fn fails_for_missing_interface() {
+        assert!(Stats::for_interface("lsoup-missing0").is_err());
+        assert!(LinkState::for_interface("lsoup-missing0").is_err());
+    }
+}
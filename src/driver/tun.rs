@@ -1,29 +1,168 @@
-use std::{cmp, ffi, fs, io, mem, process};
+use std::{fs, io, mem};
+use std::ffi::CStr;
 use std::io::{Read, Write};
-use std::os::unix::io::{AsRawFd, RawFd};
+use std::net::Ipv4Addr;
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::sync::{Arc, Mutex};
 
 use libc;
-use libc::{c_char, c_short, c_int};
+use libc::{c_char, c_short, c_int, c_uint, c_ulong};
 
-use driver::{cvt};
-use driver::{IoctlFlags, TunFlags, TUN_PATH};
+use driver::{cvt, Address, Config, Error, Filter, LinkState, Netns, Result, Stats, Step};
+use driver::netlink::{Netlink, RT_TABLE_MAIN};
+use driver::scm;
+use driver::{IoctlFlags, Offload, PacketInfo, TunBuilder, TunFlags, VirtioNetHdr};
+use driver::{ETHERNET_OVERHEAD, MAX_PACKET_SIZE, TUN_PATH};
 
 #[repr(C)]
-union IfrIfru {
-    ifru_flags: c_short,
-    ifru_mtu: c_int,
+pub(super) union IfrIfru {
+    pub(super) ifru_flags: c_short,
+    pub(super) ifru_mtu: c_int,
+    pub(super) ifru_ivalue: c_int,
+    pub(super) ifru_hwaddr: libc::sockaddr,
+    pub(super) ifru_pad: [u8; 24],
 }
 
 #[repr(C)]
-struct Ifreq {
-    ifr_name: [c_char; libc::IF_NAMESIZE],
-    ifr_ifru: IfrIfru,
+pub(super) struct Ifreq {
+    pub(super) ifr_name: [c_char; libc::IF_NAMESIZE],
+    pub(super) ifr_ifru: IfrIfru,
+}
+
+// Kernel state changed by `Tun::up`, kept so that `Tun::down` reverts exactly
+// that. Everything needed to revert is held here, down to the open sysctl
+// file, so that teardown opens nothing and looks nothing up.
+#[derive(Debug)]
+enum Applied {
+    Sysctl { file: fs::File, old: String },
+    LinkUp { index: c_uint },
+    Address { index: c_uint, address: Ipv4Addr, prefix_len: u8 },
+    Route { index: c_uint, gateway: Ipv4Addr, table: u32 },
+    Rule { priority: u32, table: u32, iif: Option<String> },
+}
+
+impl Applied {
+    fn step(&self) -> Step {
+        match *self {
+            Applied::Sysctl { .. } => Step::AcceptLocal,
+            Applied::LinkUp { .. } => Step::Link,
+            Applied::Address { .. } => Step::Address,
+            Applied::Route { .. } => Step::Route,
+            Applied::Rule { iif: Some(_), .. } => Step::InterfaceRule,
+            Applied::Rule { iif: None, .. } => Step::DefaultRule,
+        }
+    }
+}
+
+// Mirrors the kernel's dev_valid_name, plus its rule that a template such
+// as `tun%d` holds exactly one `%d`. An empty name lets the kernel pick one.
+fn valid_name(name: &str) -> bool {
+    let template = name.matches('%').count();
+    name.len() < libc::IF_NAMESIZE
+        && name != "." && name != ".."
+        && !name.chars().any(|c| c == '\0' || c == '/' || c == ':' || c.is_whitespace())
+        && (template == 0 || (template == 1 && name.contains("%d")))
+}
+
+// The kernel wants a NUL-terminated name of at most IF_NAMESIZE-1 bytes; a
+// longer name is refused rather than truncated into someone else's interface.
+pub(super) fn ifr_name(name: &str) -> Result<[c_char; libc::IF_NAMESIZE]> {
+    let bytes: &[u8] = name.as_ref();
+    if !valid_name(name) {
+        return Err(Error::Name(name.to_string()));
+    }
+
+    let mut buffer = [0u8; libc::IF_NAMESIZE];
+    buffer[..bytes.len()].clone_from_slice(bytes);
+    Ok(unsafe {
+        mem::transmute::
+            <[u8; libc::IF_NAMESIZE], [c_char; libc::IF_NAMESIZE]>
+            (buffer)
+    })
+}
+
+fn ifreq_name(ifreq: &Ifreq) -> Result<String> {
+    let name = unsafe {
+                CStr::from_ptr(ifreq.ifr_name.as_ptr())
+    };
+    Ok(name.to_str()
+           .map_err(|_| Error::Name(name.to_string_lossy().into_owned()))?
+           .to_string())
+}
+
+pub(super) fn ioctl(fd: RawFd, request: IoctlFlags, ifreq: &mut Ifreq) -> Result<()> {
+    cvt(unsafe { libc::ioctl(fd, request.bits, ifreq as *mut Ifreq) })
+        .map_err(|e| Error::ioctl(request.name(), e))?;
+    Ok(())
+}
+
+// TUN devices carry IP packets, TAP devices carry Ethernet frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Tun,
+    Tap,
+}
+
+impl Mode {
+    pub(super) fn flags(self) -> TunFlags {
+        match self {
+            Mode::Tun => TunFlags::IFF_TUN,
+            Mode::Tap => TunFlags::IFF_TAP,
+        }
+    }
+}
+
+// Runs `f` inside `netns`, or right here for an interface in our own
+// namespace.
+fn in_netns<T, F>(netns: Option<&Netns>, f: F) -> Result<T>
+    where T: Send, F: FnOnce() -> Result<T> + Send {
+    match netns {
+        Some(netns) => netns.run(f),
+        None => f(),
+    }
+}
+
+// Sockets opened in the namespace of the interface along with its first
+// queue and shared by all of them. Interface ioctls and netlink requests go
+// through these, so that neither queries nor `down` enter the namespace
+// again, and teardown needs no new fds.
+#[derive(Debug)]
+struct Sockets {
+    netlink: Mutex<Netlink>,
+    ioctl: OwnedFd,
+}
+
+impl Sockets {
+    fn new() -> io::Result<Self> {
+        let netlink = Netlink::new()?;
+        let ioctl = cvt(unsafe {
+            libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0)
+        })?;
+        Ok(Sockets {
+            netlink: Mutex::new(netlink),
+            ioctl: unsafe { OwnedFd::from_raw_fd(ioctl) },
+        })
+    }
 }
 
 #[derive(Debug)]
 pub struct Tun {
 	name: String,
+    flags: TunFlags,
     file: fs::File,
+    applied: Vec<Applied>,
+    netns: Option<Netns>,
+    sockets: Arc<Sockets>,
+}
+
+// A persistent interface outlives the process, its configuration does not,
+// unless `forget_config` says otherwise.
+impl Drop for Tun {
+    fn drop(&mut self) {
+        let _ = self.down();
+    }
 }
 
 impl AsRawFd for Tun {
@@ -32,6 +171,15 @@ impl AsRawFd for Tun {
     }
 }
 
+// Panics if `fd` is not a TUN/TAP fd attached to an interface. Use
+// `Tun::from_file` or `Tun::recv_from`, which return an error instead, for
+// fds handed over by someone else.
+impl FromRawFd for Tun {
+    unsafe fn from_raw_fd(fd: RawFd) -> Self {
+        Tun::from_file(fs::File::from_raw_fd(fd)).expect("fd is not attached to a TUN/TAP interface")
+    }
+}
+
 impl Read for Tun {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         self.file.read(buf)
@@ -50,111 +198,511 @@ impl Write for Tun {
 
 impl Tun {
 
-    pub fn new(name: &str) -> io::Result<Self> {
-        let file = try!(fs::OpenOptions::new()
-                                        .read(true)
-                                        .write(true)
-                                        .open(TUN_PATH));
-    
+    pub fn builder() -> TunBuilder {
+        TunBuilder::new()
+    }
+
+    pub fn new(name: &str) -> Result<Self> {
+        Tun::with_mode(name, Mode::Tun)
+    }
+
+    pub fn with_mode(name: &str, mode: Mode) -> Result<Self> {
+        TunBuilder::new().name(name).mode(mode).build()
+    }
+
+    // Creates the first queue of a multi-queue interface, more queues are
+    // added with `open_queue`.
+    pub fn multi_queue(name: &str, mode: Mode) -> Result<Self> {
+        TunBuilder::new().name(name).mode(mode).multi_queue(true).build()
+    }
+
+    // Every packet read or written is prefixed with a `VirtioNetHdr`, which
+    // lets the kernel pass GSO super-packets and partial checksums.
+    pub fn with_vnet_hdr(name: &str, mode: Mode) -> Result<Self> {
+        TunBuilder::new().name(name).mode(mode).vnet_hdr(true).build()
+    }
+
+    // Keeps the `PacketInfo` header in front of every packet, see
+    // `read_packet` and `write_packet`.
+    pub fn with_packet_info(name: &str, mode: Mode) -> Result<Self> {
+        TunBuilder::new().name(name).mode(mode).packet_info(true).build()
+    }
+
+    // `name` may be a template such as `tun%d`, the name the kernel picked
+    // is returned by `get_name`.
+    // Within `netns`, the interface is created there.
+    pub(super) fn open(name: &str, flags: TunFlags, netns: Option<&Netns>) -> Result<Self> {
+        let mut tun = in_netns(netns, || Tun::open_here(name, flags, None))?;
+        tun.netns = netns.cloned();
+        Ok(tun)
+    }
+
+    // Queues of an interface share the sockets of the first.
+    fn open_here(name: &str, flags: TunFlags, sockets: Option<Arc<Sockets>>) -> Result<Self> {
+        let file = fs::OpenOptions::new()
+                        .read(true)
+                        .write(true)
+                        .open(TUN_PATH)
+                        .map_err(Error::open)?;
+
         let mut ifreq = Ifreq {
-            ifr_name: {
-                let mut buffer = [0u8; libc::IF_NAMESIZE];
-                let bytes: &[u8] = name.as_ref();
-                let len = cmp::min(bytes.len(), libc::IF_NAMESIZE-1);
-                buffer[..len].clone_from_slice(&bytes[..len]);
-                unsafe {
-                    mem::transmute::
-                        <[u8; libc::IF_NAMESIZE], [c_char; libc::IF_NAMESIZE]>
-                        (buffer)
-                }
-            },
+            ifr_name: ifr_name(name)?,
             ifr_ifru: IfrIfru {
-                ifru_flags: {
-                    TunFlags::IFF_TUN.bits | TunFlags::IFF_NO_PI.bits
-                },
+                ifru_flags: flags.bits,
             },
         };
-    
-        cvt(unsafe {
-            libc::ioctl(file.as_raw_fd(),
-                        IoctlFlags::TUNSETIFF.bits,
-                        &mut ifreq)
-        })?;
 
-        let name = unsafe {
-                    ffi::CStr::from_ptr(ifreq.ifr_name.as_ptr() as *const i8)
+        ioctl(file.as_raw_fd(), IoctlFlags::TUNSETIFF, &mut ifreq)?;
+        let sockets = match sockets {
+            Some(sockets) => sockets,
+            None => Arc::new(Sockets::new()?),
+        };
+
+        Ok(Tun {
+            name: ifreq_name(&ifreq)?,
+            flags,
+            file,
+            applied: Vec::new(),
+            netns: None,
+            sockets,
+        })
+    }
+
+    // Adopts an fd that is already attached to an interface, e.g. opened by a
+    // privileged launcher. Name, mode and headers are queried with TUNGETIFF,
+    // so the caller needs no privileges at all. Packets can be read and
+    // written wherever the interface lives, but interface ioctls, stats and
+    // `up`/`down` go through sockets of the caller's own namespace and only
+    // work if the interface is there too.
+    pub fn from_file(file: fs::File) -> Result<Self> {
+        let mut ifreq = Ifreq {
+            ifr_name: [0; libc::IF_NAMESIZE],
+            ifr_ifru: IfrIfru { ifru_flags: 0 },
         };
-        let name = name.to_owned().into_string().unwrap();
 
+        ioctl(file.as_raw_fd(), IoctlFlags::TUNGETIFF, &mut ifreq)?;
+        let name = ifreq_name(&ifreq)?;
+
+        // The flags come from the fd itself, the interface may live in a
+        // namespace where its name means something else.
+        let flags = TunFlags::from_bits_truncate(unsafe { ifreq.ifr_ifru.ifru_flags });
         Ok(Tun {
-            name: name,
-            file: file,
-        })        
+            name,
+            flags: flags - TunFlags::IFF_PERSIST - TunFlags::IFF_DETACH_QUEUE,
+            file,
+            applied: Vec::new(),
+            netns: None,
+            sockets: Arc::new(Sockets::new()?),
+        })
+    }
+
+    // Receives the device fd over `socket`, see `send_to`.
+    pub fn recv_from(socket: &UnixStream) -> Result<Self> {
+        let fd = scm::recv_fd(socket)?;
+        Tun::from_file(unsafe { fs::File::from_raw_fd(fd) })
+    }
+
+    // Hands a duplicate of the device fd to the peer of `socket` with
+    // SCM_RIGHTS. This `Tun` stays usable and still owns the configuration.
+    pub fn send_to(&self, socket: &UnixStream) -> Result<()> {
+        scm::send_fd(socket, self.as_raw_fd())?;
+        Ok(())
+    }
+
+    // Opens one more queue on this multi-queue interface. The new queue does
+    // not own the interface configuration, only the `Tun` that ran `up` does.
+    pub fn open_queue(&self) -> Result<Self> {
+        if !self.flags.contains(TunFlags::IFF_MULTI_QUEUE) {
+            return Err(Error::Io(io::Error::new(io::ErrorKind::InvalidInput,
+                                                format!("{} is not a multi-queue interface", self.name))));
+        }
+        let sockets = self.sockets.clone();
+        let mut tun = in_netns(self.netns.as_ref(), || Tun::open_here(&self.name, self.flags, Some(sockets)))?;
+        tun.netns = self.netns.clone();
+        Ok(tun)
+    }
+
+    // A detached queue stays open but the kernel stops steering packets to it.
+    pub fn attach_queue(&self) -> Result<()> {
+        self.set_queue(TunFlags::IFF_ATTACH_QUEUE)
+    }
+
+    pub fn detach_queue(&self) -> Result<()> {
+        self.set_queue(TunFlags::IFF_DETACH_QUEUE)
+    }
+
+    // Keeps the interface after the last fd is closed, so that it can be
+    // attached to again by name, e.g. by an unprivileged owner.
+    pub fn set_persist(&mut self, persist: bool) -> Result<()> {
+        self.set_int(IoctlFlags::TUNSETPERSIST, persist as c_ulong)
+    }
+
+    // Lets `uid` attach to the interface without CAP_NET_ADMIN.
+    pub fn set_owner(&self, uid: libc::uid_t) -> Result<()> {
+        self.set_int(IoctlFlags::TUNSETOWNER, uid as c_ulong)
+    }
+
+    // Lets members of `gid` attach to the interface without CAP_NET_ADMIN.
+    pub fn set_group(&self, gid: libc::gid_t) -> Result<()> {
+        self.set_int(IoctlFlags::TUNSETGROUP, gid as c_ulong)
+    }
+
+    fn set_int(&self, request: IoctlFlags, value: c_ulong) -> Result<()> {
+        cvt(unsafe { libc::ioctl(self.as_raw_fd(), request.bits, value) })
+            .map_err(|e| Error::ioctl(request.name(), e))?;
+        Ok(())
+    }
+
+    pub fn has_vnet_hdr(&self) -> bool {
+        self.flags.contains(TunFlags::IFF_VNET_HDR)
+    }
+
+    pub fn has_packet_info(&self) -> bool {
+        !self.flags.contains(TunFlags::IFF_NO_PI)
+    }
+
+    // Reads one packet into `buf` and returns its header separately.
+    pub fn read_packet(&mut self, buf: &mut [u8]) -> io::Result<(PacketInfo, usize)> {
+        if !self.has_packet_info() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("{} has no packet information header", self.name)));
+        }
+
+        let mut pi = [0u8; PacketInfo::LEN];
+        let iov = [
+            libc::iovec { iov_base: pi.as_mut_ptr() as *mut _, iov_len: pi.len() },
+            libc::iovec { iov_base: buf.as_mut_ptr() as *mut _, iov_len: buf.len() },
+        ];
+        let len = cvt(unsafe { libc::readv(self.as_raw_fd(), iov.as_ptr(), iov.len() as c_int) })?;
+        if (len as usize) < PacketInfo::LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "short packet information header"));
+        }
+
+        Ok((PacketInfo::parse(&pi).unwrap(), len as usize - PacketInfo::LEN))
+    }
+
+    // Writes `packet` behind `info`, returning the number of packet bytes written.
+    pub fn write_packet(&mut self, info: &PacketInfo, packet: &[u8]) -> io::Result<usize> {
+        if !self.has_packet_info() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("{} has no packet information header", self.name)));
+        }
+
+        let pi = info.to_bytes();
+        let iov = [
+            libc::iovec { iov_base: pi.as_ptr() as *mut _, iov_len: pi.len() },
+            libc::iovec { iov_base: packet.as_ptr() as *mut _, iov_len: packet.len() },
+        ];
+        let len = cvt(unsafe { libc::writev(self.as_raw_fd(), iov.as_ptr(), iov.len() as c_int) })?;
+        Ok((len as usize).saturating_sub(PacketInfo::LEN))
+    }
+
+    pub fn set_offload(&self, offload: Offload) -> Result<()> {
+        if !offload.is_empty() && !self.has_vnet_hdr() {
+            return Err(Error::Io(io::Error::new(io::ErrorKind::InvalidInput,
+                                                format!("{} has no vnet header", self.name))));
+        }
+
+        self.set_int(IoctlFlags::TUNSETOFFLOAD, offload.bits as c_ulong)
+    }
+
+    // Drops packets not matching `filter` in the kernel, before they are
+    // queued to any of our fds. The kernel only filters TAP interfaces.
+    pub fn attach_filter(&self, filter: &Filter) -> Result<()> {
+        if self.get_mode() != Mode::Tap || filter.get_mode() != Mode::Tap {
+            return Err(Error::Io(io::Error::new(io::ErrorKind::InvalidInput,
+                                                format!("{} needs a TAP interface and filter", self.name))));
+        }
+
+        let fprog = filter.as_fprog();
+        self.set_int(IoctlFlags::TUNATTACHFILTER, &fprog as *const _ as c_ulong)
+    }
+
+    pub fn detach_filter(&self) -> Result<()> {
+        self.set_int(IoctlFlags::TUNDETACHFILTER, 0)
+    }
+
+    // Turning the carrier off takes the interface operationally down, as if
+    // its cable was pulled, while it stays administratively up.
+    pub fn set_carrier(&self, carrier: bool) -> Result<()> {
+        let carrier = carrier as c_int;
+        self.set_int(IoctlFlags::TUNSETCARRIER, &carrier as *const c_int as c_ulong)
+    }
+
+    fn netlink(&self) -> ::std::sync::MutexGuard<'_, Netlink> {
+        self.sockets.netlink.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn get_link_state(&self) -> Result<LinkState> {
+        Ok(LinkState::from_link(&self.netlink().link(&self.name)?))
+    }
+
+    pub fn get_stats(&self) -> Result<Stats> {
+        Ok(Stats::from_link(&self.netlink().link(&self.name)?))
+    }
+
+    // None while the interface is in the namespace we were started in.
+    pub fn get_netns(&self) -> Option<&Netns> {
+        self.netns.as_ref()
+    }
+
+    // Moving drops the addresses and routes of an interface, so it has to
+    // happen before `up`. Queues opened later follow it.
+    pub fn move_to_netns(&mut self, netns: &Netns) -> Result<()> {
+        if !self.applied.is_empty() {
+            return Err(Error::Io(io::Error::new(io::ErrorKind::InvalidInput,
+                                                format!("{} is configured, call down first", self.name))));
+        }
+
+        let index = self.get_index()?;
+        self.netlink().set_link_netns(index, netns.as_raw_fd())
+            .map_err(|e| Error::system("move interface", e))?;
+        self.sockets = Arc::new(netns.run(|| Ok(Sockets::new()?))?);
+        self.netns = Some(netns.clone());
+        Ok(())
+    }
+
+    fn set_queue(&self, flags: TunFlags) -> Result<()> {
+        let mut ifreq = Ifreq {
+            ifr_name: [0; libc::IF_NAMESIZE],
+            ifr_ifru: IfrIfru { ifru_flags: flags.bits },
+        };
+
+        ioctl(self.as_raw_fd(), IoctlFlags::TUNSETQUEUE, &mut ifreq)
     }
 
 	pub fn get_name(&self) -> &str {
 		&self.name
 	}
 
-	pub fn get_mtu(&self) -> io::Result<usize> {
+    fn get_index(&self) -> Result<c_uint> {
         let mut ifreq = Ifreq {
-            ifr_name: {
-                let mut buffer = [0u8; libc::IF_NAMESIZE];
-                let bytes: &[u8] = self.name.as_ref();
-                let len = cmp::min(bytes.len(), libc::IF_NAMESIZE-1);
-                buffer[..len].clone_from_slice(&bytes[..len]);
-                unsafe {
-                    mem::transmute::
-                        <[u8; libc::IF_NAMESIZE], [c_char; libc::IF_NAMESIZE]>
-                        (buffer)
-                }
-		    },
+            ifr_name: ifr_name(&self.name)?,
+            ifr_ifru: IfrIfru { ifru_ivalue: 0 },
+        };
+
+        self.socket_ioctl(IoctlFlags::SIOCGIFINDEX, &mut ifreq)?;
+        Ok(unsafe { ifreq.ifr_ifru.ifru_ivalue } as c_uint)
+    }
+
+    // Large enough for the biggest packet one read can hand over, whatever
+    // the MTU is now or is changed to later, e.g. with `ip link`.
+    pub fn get_buffer_size(&self) -> usize {
+        let size = if self.has_vnet_hdr() {
+            VirtioNetHdr::LEN + MAX_PACKET_SIZE
+        } else {
+            MAX_PACKET_SIZE
+        };
+        let size = match self.get_mode() {
+            Mode::Tun => size,
+            Mode::Tap => size + ETHERNET_OVERHEAD,
+        };
+        if self.has_packet_info() {
+            size + PacketInfo::LEN
+        } else {
+            size
+        }
+    }
+
+    pub fn get_mode(&self) -> Mode {
+        if self.flags.contains(TunFlags::IFF_TAP) {
+            Mode::Tap
+        } else {
+            Mode::Tun
+        }
+    }
+
+    // Issues an interface ioctl such as SIOCGIFMTU, which finds the interface
+    // by name in the namespace of the socket.
+    fn socket_ioctl(&self, request: IoctlFlags, ifreq: &mut Ifreq) -> Result<()> {
+        ioctl(self.sockets.ioctl.as_raw_fd(), request, ifreq)
+    }
+
+	pub fn get_mtu(&self) -> Result<usize> {
+        let mut ifreq = Ifreq {
+            ifr_name: ifr_name(&self.name)?,
             ifr_ifru: IfrIfru { ifru_mtu: 0 },
         };
 
-		unsafe {
-			let sock = cvt(libc::socket(libc::AF_INET, libc::SOCK_DGRAM, 0))?;
-            cvt(libc::ioctl(sock, IoctlFlags::SIOCGIFMTU.bits, &mut ifreq))?;
-            Ok(ifreq.ifr_ifru.ifru_mtu as usize)
-		}
+        self.socket_ioctl(IoctlFlags::SIOCGIFMTU, &mut ifreq)?;
+        Ok(unsafe { ifreq.ifr_ifru.ifru_mtu } as usize)
 	}
 
-    pub fn set_nonblocking(&self) -> io::Result<()> {
-        unsafe {
-            let mut flags: c_int = 0;
-            cvt(libc::fcntl(self.as_raw_fd(), libc::F_GETFL, 0))?;
+    pub fn set_mtu(&self, mtu: usize) -> Result<()> {
+        let mut ifreq = Ifreq {
+            ifr_name: ifr_name(&self.name)?,
+            ifr_ifru: IfrIfru { ifru_mtu: mtu as c_int },
+        };
+        self.socket_ioctl(IoctlFlags::SIOCSIFMTU, &mut ifreq)
+    }
+
+    pub fn get_txqueuelen(&self) -> Result<usize> {
+        let mut ifreq = Ifreq {
+            ifr_name: ifr_name(&self.name)?,
+            ifr_ifru: IfrIfru { ifru_ivalue: 0 },
+        };
+
+        self.socket_ioctl(IoctlFlags::SIOCGIFTXQLEN, &mut ifreq)?;
+        Ok(unsafe { ifreq.ifr_ifru.ifru_ivalue } as usize)
+    }
+
+    pub fn set_txqueuelen(&self, len: usize) -> Result<()> {
+        let mut ifreq = Ifreq {
+            ifr_name: ifr_name(&self.name)?,
+            ifr_ifru: IfrIfru { ifru_ivalue: len as c_int },
+        };
+        self.socket_ioctl(IoctlFlags::SIOCSIFTXQLEN, &mut ifreq)
+    }
+
+    pub fn get_mac_address(&self) -> Result<[u8; 6]> {
+        let mut ifreq = Ifreq {
+            ifr_name: ifr_name(&self.name)?,
+            ifr_ifru: IfrIfru { ifru_pad: [0; 24] },
+        };
+
+        self.socket_ioctl(IoctlFlags::SIOCGIFHWADDR, &mut ifreq)?;
+        let mut mac = [0u8; 6];
+        for (byte, data) in mac.iter_mut().zip(unsafe { ifreq.ifr_ifru.ifru_hwaddr.sa_data }.iter()) {
+            *byte = *data as u8;
+        }
+        Ok(mac)
+    }
+
+    // Only TAP devices have a link-layer address.
+    pub fn set_mac_address(&self, mac: [u8; 6]) -> Result<()> {
+        let mut hwaddr: libc::sockaddr = unsafe { mem::zeroed() };
+        hwaddr.sa_family = libc::ARPHRD_ETHER;
+        for (data, byte) in hwaddr.sa_data.iter_mut().zip(mac.iter()) {
+            *data = *byte as c_char;
+        }
+
+        let mut ifreq = Ifreq {
+            ifr_name: ifr_name(&self.name)?,
+            ifr_ifru: IfrIfru { ifru_hwaddr: hwaddr },
+        };
+        self.socket_ioctl(IoctlFlags::SIOCSIFHWADDR, &mut ifreq)
+    }
 
-            flags |= libc::O_NONBLOCK;
-            cvt(libc::fcntl(self.as_raw_fd(), libc::F_SETFL, flags))?;
+    pub fn set_nonblocking(&self) -> Result<()> {
+        unsafe {
+            let flags = cvt(libc::fcntl(self.as_raw_fd(), libc::F_GETFL, 0))?;
+            cvt(libc::fcntl(self.as_raw_fd(), libc::F_SETFL, flags | libc::O_NONBLOCK))?;
             Ok(())
         }
     }
 
-    fn execute_command(&self, commands: &[String]) {
-        for ref command in commands {
-            let command: Vec<&str> = command.split_whitespace().collect();
+    // Inside the namespace of the interface, if it has one. On failure only
+    // what this call applied is reverted, an earlier `up` stays in place.
+    #[cfg(target_os = "linux")]
+    pub fn up(&mut self, config: &Config) -> Result<()> {
+        let applied = self.applied.len();
+        let result = self.apply(config);
+        if result.is_err() {
+            let _ = self.revert_to(applied);
+        }
+        result
+    }
+
+    fn apply(&mut self, config: &Config) -> Result<()> {
+        let sockets = self.sockets.clone();
+        let mut netlink = sockets.netlink.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        netlink.rules()
+            .and_then(|rules| config.validate(&rules))
+            .map_err(|e| Error::config(Step::Validate, e))?;
+
+        // /proc/sys/net shows the namespace of whoever opens it.
+        let path = format!("/proc/sys/net/ipv4/conf/{}/accept_local", self.name);
+        let mut file = in_netns(self.netns.as_ref(), || {
+            Ok(fs::OpenOptions::new().read(true).write(true).open(&path)?)
+        }).map_err(|e| Error::config(Step::AcceptLocal, e.into()))?;
+        // What was already so, e.g. after an earlier `up`, is not recorded,
+        // so that rolling back a failed `up` leaves it alone.
+        let mut old = String::new();
+        file.read_to_string(&mut old)
+            .and_then(|_| file.write_at(b"1", 0))
+            .map_err(|e| Error::config(Step::AcceptLocal, e))?;
+        if old.trim() != "1" {
+            self.applied.push(Applied::Sysctl { file, old });
+        }
+
+        let index = self.get_index().map_err(|e| Error::config(Step::Link, e.into()))?;
+
+        let was_up = netlink.link(&self.name)
+            .map_err(|e| Error::config(Step::Link, e))?
+            .flags & libc::IFF_UP as u32 != 0;
+        netlink.set_link_up(index, true)
+            .map_err(|e| Error::config(Step::Link, e))?;
+        if !was_up {
+            self.applied.push(Applied::LinkUp { index });
+        }
+
+        for &Address { address, prefix_len } in &config.addresses {
+            netlink.add_address(index, address, prefix_len)
+                .map_err(|e| Error::config(Step::Address, e))?;
+            self.applied.push(Applied::Address { index, address, prefix_len });
+        }
+
+        let (gateway, table) = (config.gateway().unwrap(), config.table);
+        netlink.add_default_route(index, gateway, table)
+            .map_err(|e| Error::config(Step::Route, e))?;
+        self.applied.push(Applied::Route { index, gateway, table });
 
-            let status = process::Command::new(command[0])
-                                .args(&command[1..])
-                                .status()
-                                .unwrap();
+        let priority = config.interface_rule_priority;
+        netlink.add_rule(priority, RT_TABLE_MAIN, Some(&self.name))
+            .map_err(|e| Error::config(Step::InterfaceRule, e))?;
+        self.applied.push(Applied::Rule { priority, table: RT_TABLE_MAIN, iif: Some(self.name.clone()) });
 
-            assert!(status.success());
+        if config.default_rule {
+            let priority = config.default_rule_priority;
+            netlink.add_rule(priority, table, None)
+                .map_err(|e| Error::config(Step::DefaultRule, e))?;
+            self.applied.push(Applied::Rule { priority, table, iif: None });
         }
+
+        Ok(())
+    }
+
+    // Leaves what `up` applied in place once this `Tun` is dropped, e.g. for a
+    // persistent interface that should stay configured after the process.
+    pub fn forget_config(&mut self) {
+        self.applied.clear();
     }
 
-    // TODO: use netlink
+    // Reverts everything `up` applied, newest first. Every step is attempted
+    // and the first failure is returned.
     #[cfg(target_os = "linux")]
-    pub fn up(&self) {
-        let commands = [
-            format!("sysctl -w net.ipv4.conf.{}.accept_local=1", self.name),
-            format!("ip link set {} up", self.name),
-            format!("ip addr add 172.32.0.1/24 dev {}", self.name),
-            format!("ip route add default via 172.32.0.1 dev {} table 100", self.name),
-            format!("ip rule add from all iif {} pref 10 lookup main", self.name),
-            format!("ip rule add from all pref 100 lookup 100"),
-        ];
-        self.execute_command(&commands);
+    pub fn down(&mut self) -> Result<()> {
+        self.revert_to(0)
+    }
+
+    // Reverts the entries of `applied` from `len` on.
+    fn revert_to(&mut self, len: usize) -> Result<()> {
+        let mut result = Ok(());
+        while self.applied.len() > len {
+            let applied = self.applied.pop().unwrap();
+            let step = applied.step();
+            if let Err(e) = self.revert(applied) {
+                if result.is_ok() {
+                    result = Err(Error::revert(step, e));
+                }
+            }
+        }
+        result
+    }
+
+    fn revert(&self, applied: Applied) -> io::Result<()> {
+        match applied {
+            Applied::Sysctl { file, old } =>
+                file.write_at(old.as_bytes(), 0).map(|_| ()),
+            Applied::LinkUp { index } =>
+                self.netlink().set_link_up(index, false),
+            Applied::Address { index, address, prefix_len } =>
+                self.netlink().del_address(index, address, prefix_len),
+            Applied::Route { index, gateway, table } =>
+                self.netlink().del_default_route(index, gateway, table),
+            Applied::Rule { priority, table, iif } =>
+                self.netlink().del_rule(priority, table, iif.as_deref()),
+        }
     }
 
 }
@@ -0,0 +1,305 @@
+/*
+ * Reference:
+ *  https://kernel.dk/io_uring.pdf
+ *  https://man7.org/linux/man-pages/man2/io_uring_register.2.html
+ *
+ * Date: Oct 18 CST 2026
+ */
+
+use std::collections::VecDeque;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+use io_uring::{opcode, types, IoUring};
+use libc;
+use libc::c_void;
+use mio::{Evented, Ready, Poll, PollOpt, Registration, SetReadiness, Token};
+use mio::unix::EventedFd;
+
+use driver::{cvt, Device, Iface, Mode, PacketInfo, Result, VirtioNetHdr};
+
+// user_data of cancellations issued on drop; reads use their slot index,
+// writes `depth` plus theirs.
+const CANCEL: u64 = u64::MAX;
+
+/*
+    Registered buffers, one slot of `buffer_size` bytes each:
+        +--------+-----+------------+---------+-----+----------------+
+        | read 0 | ... | read N-1   | write 0 | ... | write N-1      |
+        +--------+-----+------------+---------+-----+----------------+
+    Every read slot always has a READ_FIXED in flight, except while its
+    packet waits in `ready`. Completions are signalled through an eventfd,
+    which is what gets registered with mio as readable. Writability is a
+    readiness of its own, set while a write slot is free.
+*/
+pub struct UringDevice {
+    ring: IoUring,
+    iface: Iface,
+    eventfd: RawFd,
+    buffers: Vec<u8>,
+    buffer_size: usize,
+    depth: usize,
+    ready: VecDeque<(usize, io::Result<usize>)>,
+    free_writes: Vec<usize>,
+    write_error: Option<io::Error>,
+    inflight: usize,
+    registration: Registration,
+    set_readiness: SetReadiness,
+}
+
+impl UringDevice {
+
+    // Keeps `depth` reads in flight and allows as many writes to be queued.
+    // `iface` should be blocking, the ring polls it on our behalf.
+    pub fn new(iface: Iface, depth: usize) -> Result<Self> {
+        let buffer_size = iface.get_buffer_size();
+        let ring = IoUring::new((2 * depth).next_power_of_two() as u32)?;
+        let eventfd = cvt(unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) })?;
+        let (registration, set_readiness) = Registration::new2();
+        set_readiness.set_readiness(Ready::writable())?;
+
+        let mut device = UringDevice {
+            ring,
+            iface,
+            eventfd,
+            buffers: vec![0u8; 2 * depth * buffer_size],
+            buffer_size,
+            depth,
+            ready: VecDeque::with_capacity(depth),
+            free_writes: (depth..2 * depth).collect(),
+            write_error: None,
+            inflight: 0,
+            registration,
+            set_readiness,
+        };
+
+        let iovecs = device.buffers.chunks_mut(buffer_size)
+            .map(|slot| libc::iovec { iov_base: slot.as_mut_ptr() as *mut _, iov_len: slot.len() })
+            .collect::<Vec<_>>();
+        {
+            let submitter = device.ring.submitter();
+            // The buffers live as long as the ring, see the field order.
+            unsafe { submitter.register_buffers(&iovecs)? };
+            submitter.register_files(&[device.iface.as_raw_fd()])?;
+            submitter.register_eventfd(eventfd)?;
+        }
+
+        for slot in 0..depth {
+            device.push_read(slot)?;
+        }
+        device.ring.submit()?;
+        Ok(device)
+    }
+
+    pub fn get_ref(&self) -> &Iface {
+        &self.iface
+    }
+
+    fn slot(&mut self, slot: usize) -> &mut [u8] {
+        let size = self.buffer_size;
+        &mut self.buffers[slot * size..(slot + 1) * size]
+    }
+
+    fn push(&mut self, entry: &io_uring::squeue::Entry) -> io::Result<()> {
+        unsafe { self.ring.submission().push(entry) }
+            .map_err(|_| io::Error::other("submission queue is full"))?;
+        self.inflight += 1;
+        Ok(())
+    }
+
+    fn push_read(&mut self, slot: usize) -> io::Result<()> {
+        let (ptr, len) = (self.slot(slot).as_mut_ptr(), self.buffer_size as u32);
+        let entry = opcode::ReadFixed::new(types::Fixed(0), ptr, len, slot as u16)
+            .build()
+            .user_data(slot as u64);
+        self.push(&entry)
+    }
+
+    // Moves completions out of the ring: read slots into `ready`, write slots
+    // back to `free_writes`, keeping the first write error. Freed write slots
+    // make the device writable again.
+    fn reap(&mut self) {
+        let completions = self.ring.completion()
+            .map(|cqe| (cqe.user_data(), cqe.result()))
+            .collect::<Vec<_>>();
+
+        for (user_data, result) in completions {
+            self.inflight -= 1;
+            if user_data == CANCEL {
+                continue;
+            }
+
+            let slot = user_data as usize;
+            let result = if result < 0 {
+                Err(io::Error::from_raw_os_error(-result))
+            } else {
+                Ok(result as usize)
+            };
+            if slot < self.depth {
+                self.ready.push_back((slot, result));
+            } else {
+                self.free_writes.push(slot);
+                if let (Err(e), None) = (result, self.write_error.as_ref()) {
+                    self.write_error = Some(e);
+                }
+            }
+        }
+        if !self.free_writes.is_empty() {
+            let _ = self.set_readiness.set_readiness(Ready::writable());
+        }
+    }
+
+    fn clear_eventfd(&self) {
+        let mut counter = 0u64;
+        unsafe { libc::read(self.eventfd, &mut counter as *mut u64 as *mut c_void, 8) };
+    }
+}
+
+impl Drop for UringDevice {
+    fn drop(&mut self) {
+        // The kernel may still write into the buffers until every read has
+        // completed, so cancel them and wait before the buffers are freed.
+        for slot in 0..self.depth {
+            let entry = opcode::AsyncCancel::new(slot as u64).build().user_data(CANCEL);
+            if self.push(&entry).is_err() {
+                break;
+            }
+        }
+        while self.inflight > 0 {
+            if self.ring.submit_and_wait(1).is_err() {
+                break;
+            }
+            self.reap();
+        }
+        unsafe { libc::close(self.eventfd) };
+    }
+}
+
+// Completions of writes also arrive through the eventfd, so the device is
+// always registered readable: its writability follows a `read_packet` or
+// `write_packet` that collects them. As with any mio `Registration`, the
+// device can only ever be registered with one `Poll`.
+impl Evented for UringDevice {
+
+    fn register(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt)
+        -> io::Result<()> {
+        EventedFd(&self.eventfd).register(poll, token, Ready::readable(), opts)?;
+        poll.register(&self.registration, token, interest & Ready::writable(), opts)
+    }
+
+    fn reregister(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt)
+        -> io::Result<()> {
+        EventedFd(&self.eventfd).reregister(poll, token, Ready::readable(), opts)?;
+        poll.reregister(&self.registration, token, interest & Ready::writable(), opts)
+    }
+
+    fn deregister(&self, poll: &Poll) -> io::Result<()> {
+        EventedFd(&self.eventfd).deregister(poll)?;
+        poll.deregister(&self.registration)
+    }
+}
+
+// Like `MioWrapper`, the packet information header is stripped on read,
+// returned by `read_packet_info`, and derived from the packet on write.
+// Writes return once queued; an error of a queued write is returned by the
+// next `write_packet`.
+impl Device for UringDevice {
+    fn get_mode(&self) -> Mode {
+        self.iface.get_mode()
+    }
+
+    fn get_mtu(&self) -> io::Result<usize> {
+        Ok(self.iface.get_mtu()?)
+    }
+
+    fn get_buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    fn has_vnet_hdr(&self) -> bool {
+        self.iface.has_vnet_hdr()
+    }
+
+    fn read_packet(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_packet_info(buf).map(|(len, _)| len)
+    }
+
+    fn read_packet_info(&mut self, buf: &mut [u8]) -> io::Result<(usize, Option<PacketInfo>)> {
+        self.reap();
+        if self.ready.is_empty() {
+            self.clear_eventfd();
+            self.reap();
+        }
+        let (slot, result) = self.ready.pop_front().ok_or(io::ErrorKind::WouldBlock)?;
+
+        let offset = if self.iface.has_packet_info() { PacketInfo::LEN } else { 0 };
+        let copied = result.and_then(|len| {
+            let packet = &self.slot(slot)[..len];
+            let info = if offset > 0 { PacketInfo::parse(packet) } else { None };
+            if offset > 0 && info.is_none_or(|info| info.is_truncated()) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          format!("packet truncated to {} bytes", len)));
+            }
+            let packet = &packet[offset..];
+            if packet.len() > buf.len() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          format!("packet of {} bytes truncated to {}", packet.len(), buf.len())));
+            }
+            buf[..packet.len()].copy_from_slice(packet);
+            Ok((packet.len(), info))
+        });
+
+        self.push_read(slot)?;
+        self.ring.submit()?;
+        copied
+    }
+
+    fn write_packet(&mut self, packet: &[u8]) -> io::Result<usize> {
+        if let Some(e) = self.write_error.take() {
+            return Err(e);
+        }
+
+        let info = if self.iface.has_packet_info() {
+            let payload = if self.iface.has_vnet_hdr() {
+                packet.get(VirtioNetHdr::LEN..).unwrap_or(&[])
+            } else {
+                packet
+            };
+            Some(PacketInfo::for_packet(self.iface.get_mode(), payload)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "unknown packet protocol"))?)
+        } else {
+            None
+        };
+        let offset = if info.is_some() { PacketInfo::LEN } else { 0 };
+        if offset + packet.len() > self.buffer_size {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("packet of {} bytes exceeds buffer size {}", packet.len(), self.buffer_size)));
+        }
+
+        if self.free_writes.is_empty() {
+            self.reap();
+        }
+        let slot = match self.free_writes.pop() {
+            Some(slot) => slot,
+            None => {
+                self.set_readiness.set_readiness(Ready::empty())?;
+                return Err(io::ErrorKind::WouldBlock.into());
+            },
+        };
+
+        let len = offset + packet.len();
+        {
+            let buf = self.slot(slot);
+            if let Some(info) = info {
+                info.write(buf);
+            }
+            buf[offset..len].copy_from_slice(packet);
+        }
+        let entry = opcode::WriteFixed::new(types::Fixed(0), self.slot(slot).as_ptr(), len as u32, slot as u16)
+            .build()
+            .user_data(slot as u64);
+        self.push(&entry)?;
+        self.ring.submit()?;
+        Ok(packet.len())
+    }
+}
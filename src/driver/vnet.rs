@@ -0,0 +1,455 @@
+/*
+ * Reference:
+ *  https://github.com/torvalds/linux/blob/master/include/uapi/linux/virtio_net.h
+ *  https://github.com/torvalds/linux/blob/master/net/ipv4/tcp_offload.c
+ *
+ * Date: Oct 18 CST 2026
+ */
+
+use std::io;
+
+use driver::Mode;
+use packet::checksum;
+use packet::{ETHERTYPE_IPV4, ETHERTYPE_IPV6, ETHERTYPE_VLAN};
+
+pub const VIRTIO_NET_HDR_F_NEEDS_CSUM: u8 = 1;
+pub const VIRTIO_NET_HDR_F_DATA_VALID: u8 = 2;
+
+pub const VIRTIO_NET_HDR_GSO_NONE: u8 = 0;
+pub const VIRTIO_NET_HDR_GSO_TCPV4: u8 = 1;
+pub const VIRTIO_NET_HDR_GSO_UDP: u8 = 3;
+pub const VIRTIO_NET_HDR_GSO_TCPV6: u8 = 4;
+pub const VIRTIO_NET_HDR_GSO_ECN: u8 = 0x80;
+
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+
+const TCP_FLAG_FIN: u8 = 0x01;
+const TCP_FLAG_PSH: u8 = 0x08;
+const TCP_FLAG_CWR: u8 = 0x80;
+
+const IPV4_FLAG_MF: u16 = 0x2000;
+const IPV6_HEADER_LEN: usize = 40;
+const UDP_HEADER_LEN: usize = 8;
+
+/*
+    struct virtio_net_hdr, prepended to every packet when IFF_VNET_HDR is set:
+         0         7 8        15 16                  31
+        +-----------+-----------+----------------------+
+        |   Flags   | GSO_type  |       Hdr_len        |
+        +-----------+-----------+----------------------+
+        |       GSO_size        |      Csum_start      |
+        +-----------------------+----------------------+
+        |      Csum_offset      |
+        +-----------------------+
+*/
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct VirtioNetHdr {
+    pub flags: u8,
+    pub gso_type: u8,
+    pub hdr_len: u16,
+    pub gso_size: u16,
+    pub csum_start: u16,
+    pub csum_offset: u16,
+}
+
+fn u16_at(buf: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn u32_at(buf: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([buf[offset], buf[offset + 1], buf[offset + 2], buf[offset + 3]])
+}
+
+fn set_u16_at(buf: &mut [u8], offset: usize, value: u16) {
+    buf[offset..offset + 2].clone_from_slice(&value.to_be_bytes());
+}
+
+fn set_u32_at(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].clone_from_slice(&value.to_be_bytes());
+}
+
+fn malformed(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+impl VirtioNetHdr {
+    pub const LEN: usize = 10;
+
+    // The header is in host byte order, as the tun driver uses without TUNSETVNETLE.
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() < Self::LEN {
+            return None;
+        }
+
+        let ne = |offset: usize| u16::from_ne_bytes([buf[offset], buf[offset + 1]]);
+        Some(VirtioNetHdr {
+            flags: buf[0],
+            gso_type: buf[1],
+            hdr_len: ne(2),
+            gso_size: ne(4),
+            csum_start: ne(6),
+            csum_offset: ne(8),
+        })
+    }
+
+    pub fn write(&self, buf: &mut [u8]) {
+        buf[0] = self.flags;
+        buf[1] = self.gso_type;
+        buf[2..4].clone_from_slice(&self.hdr_len.to_ne_bytes());
+        buf[4..6].clone_from_slice(&self.gso_size.to_ne_bytes());
+        buf[6..8].clone_from_slice(&self.csum_start.to_ne_bytes());
+        buf[8..10].clone_from_slice(&self.csum_offset.to_ne_bytes());
+    }
+
+    pub fn is_gso(&self) -> bool {
+        self.gso_type & !VIRTIO_NET_HDR_GSO_ECN != VIRTIO_NET_HDR_GSO_NONE
+    }
+}
+
+// Fills in a partial checksum left by the kernel: the checksum field already
+// holds the pseudo-header sum, the rest is summed from `csum_start` onwards.
+pub fn complete_checksum(hdr: &VirtioNetHdr, packet: &mut [u8]) -> io::Result<()> {
+    if hdr.flags & VIRTIO_NET_HDR_F_NEEDS_CSUM == 0 {
+        return Ok(());
+    }
+
+    let start = hdr.csum_start as usize;
+    let offset = start + hdr.csum_offset as usize;
+    if offset + 2 > packet.len() {
+        return Err(malformed("checksum offset beyond packet"));
+    }
+
+    let csum = checksum::checksum(&packet[start..]);
+    set_u16_at(packet, offset, csum);
+    Ok(())
+}
+
+// Offset of the IP header: TUN packets start with it, TAP frames carry an
+// Ethernet header and possibly one VLAN tag first.
+fn network_offset(packet: &[u8], mode: Mode) -> io::Result<usize> {
+    match mode {
+        Mode::Tun => Ok(0),
+        Mode::Tap => {
+            if packet.len() < 14 {
+                return Err(malformed("truncated ethernet header"));
+            }
+            match u16_at(packet, 12) {
+                ETHERTYPE_VLAN => Ok(18),
+                _ => Ok(14),
+            }
+        },
+    }
+}
+
+fn ip_version(packet: &[u8], l3: usize, mode: Mode) -> io::Result<u8> {
+    if packet.len() <= l3 {
+        return Err(malformed("truncated ip header"));
+    }
+    let version = packet[l3] >> 4;
+    if mode == Mode::Tap {
+        let ethertype = u16_at(packet, l3 - 2);
+        if (version == 4 && ethertype != ETHERTYPE_IPV4) || (version == 6 && ethertype != ETHERTYPE_IPV6) {
+            return Err(malformed("ip version does not match ethertype"));
+        }
+    }
+    Ok(version)
+}
+
+// Splits a GSO super-packet into packets of at most `gso_size` payload bytes,
+// each with complete IP and transport checksums. Packets that are not GSO are
+// returned as they are, with their checksum completed.
+pub fn segment(hdr: &VirtioNetHdr, packet: &[u8], mode: Mode) -> io::Result<Vec<Vec<u8>>> {
+    if !hdr.is_gso() {
+        let mut packet = packet.to_vec();
+        complete_checksum(hdr, &mut packet)?;
+        return Ok(vec![packet]);
+    }
+    if hdr.gso_size == 0 {
+        return Err(malformed("gso packet without gso_size"));
+    }
+
+    let l3 = network_offset(packet, mode)?;
+    match (hdr.gso_type & !VIRTIO_NET_HDR_GSO_ECN, ip_version(packet, l3, mode)?) {
+        (VIRTIO_NET_HDR_GSO_TCPV4, 4) => segment_tcp(packet, l3, 4, hdr.gso_size as usize),
+        (VIRTIO_NET_HDR_GSO_TCPV6, 6) => segment_tcp(packet, l3, 6, hdr.gso_size as usize),
+        (VIRTIO_NET_HDR_GSO_UDP, 4) => fragment_udp(packet, l3, hdr.gso_size as usize),
+        (gso_type, version) =>
+            Err(io::Error::new(io::ErrorKind::Unsupported,
+                               format!("unsupported gso type {} for IPv{}", gso_type, version))),
+    }
+}
+
+fn ipv4_header_len(packet: &[u8], l3: usize) -> io::Result<usize> {
+    if packet.len() < l3 + 20 {
+        return Err(malformed("truncated ipv4 header"));
+    }
+    let ihl = ((packet[l3] & 0x0f) as usize) * 4;
+    if ihl < 20 || packet.len() < l3 + ihl {
+        return Err(malformed("truncated ipv4 header"));
+    }
+    Ok(ihl)
+}
+
+fn pseudo_header_sum(packet: &[u8], l3: usize, version: u8, protocol: u8, len: usize) -> u32 {
+    if version == 4 {
+        let sum = checksum::sum(&packet[l3 + 12..l3 + 20], 0);
+        checksum::sum(&[0, protocol], sum) + len as u32
+    } else {
+        let sum = checksum::sum(&packet[l3 + 8..l3 + 40], 0);
+        checksum::sum(&(len as u32).to_be_bytes(), sum) + protocol as u32
+    }
+}
+
+fn set_ipv4_length(packet: &mut [u8], l3: usize, ihl: usize, total_len: usize) {
+    set_u16_at(packet, l3 + 2, total_len as u16);
+    set_u16_at(packet, l3 + 10, 0);
+    let csum = checksum::checksum(&packet[l3..l3 + ihl]);
+    set_u16_at(packet, l3 + 10, csum);
+}
+
+fn segment_tcp(packet: &[u8], l3: usize, version: u8, mss: usize) -> io::Result<Vec<Vec<u8>>> {
+    let l4 = match version {
+        4 => {
+            let ihl = ipv4_header_len(packet, l3)?;
+            if packet[l3 + 9] != IPPROTO_TCP {
+                return Err(malformed("tcp gso without tcp header"));
+            }
+            l3 + ihl
+        },
+        _ => {
+            if packet.len() < l3 + IPV6_HEADER_LEN || packet[l3 + 6] != IPPROTO_TCP {
+                return Err(io::Error::new(io::ErrorKind::Unsupported, "ipv6 extension headers are not supported"));
+            }
+            l3 + IPV6_HEADER_LEN
+        },
+    };
+    if packet.len() < l4 + 20 {
+        return Err(malformed("truncated tcp header"));
+    }
+    let payload_start = l4 + ((packet[l4 + 12] >> 4) as usize) * 4;
+    if payload_start < l4 + 20 || payload_start > packet.len() {
+        return Err(malformed("invalid tcp data offset"));
+    }
+
+    let seq = u32_at(packet, l4 + 4);
+    let flags = packet[l4 + 13];
+    let id = if version == 4 { u16_at(packet, l3 + 4) } else { 0 };
+    let payload = &packet[payload_start..];
+    if payload.is_empty() {
+        return Err(malformed("tcp gso without payload"));
+    }
+    let count = payload.len().div_ceil(mss);
+
+    let mut segments = Vec::with_capacity(count);
+    for (i, chunk) in payload.chunks(mss).enumerate() {
+        let mut segment = Vec::with_capacity(payload_start + chunk.len());
+        segment.extend_from_slice(&packet[..payload_start]);
+        segment.extend_from_slice(chunk);
+
+        let tcp_len = segment.len() - l4;
+        if version == 4 {
+            let ihl = l4 - l3;
+            set_u16_at(&mut segment, l3 + 4, id.wrapping_add(i as u16));
+            let total_len = segment.len() - l3;
+            set_ipv4_length(&mut segment, l3, ihl, total_len);
+        } else {
+            set_u16_at(&mut segment, l3 + 4, tcp_len as u16);
+        }
+
+        set_u32_at(&mut segment, l4 + 4, seq.wrapping_add((i * mss) as u32));
+        let mut segment_flags = flags;
+        if i != 0 {
+            segment_flags &= !TCP_FLAG_CWR;
+        }
+        if i != count - 1 {
+            segment_flags &= !(TCP_FLAG_FIN | TCP_FLAG_PSH);
+        }
+        segment[l4 + 13] = segment_flags;
+
+        set_u16_at(&mut segment, l4 + 16, 0);
+        let sum = pseudo_header_sum(&segment, l3, version, IPPROTO_TCP, tcp_len);
+        let csum = checksum::finish(checksum::sum(&segment[l4..], sum));
+        set_u16_at(&mut segment, l4 + 16, csum);
+
+        segments.push(segment);
+    }
+    Ok(segments)
+}
+
+// UFO hands over a whole UDP datagram, which goes out as IPv4 fragments of
+// at most `size` bytes of IP payload each.
+fn fragment_udp(packet: &[u8], l3: usize, size: usize) -> io::Result<Vec<Vec<u8>>> {
+    let ihl = ipv4_header_len(packet, l3)?;
+    let l4 = l3 + ihl;
+    if packet[l3 + 9] != IPPROTO_UDP || packet.len() < l4 + UDP_HEADER_LEN {
+        return Err(malformed("udp gso without udp header"));
+    }
+    let size = size & !7;
+    if size == 0 {
+        return Err(malformed("gso_size smaller than a fragment"));
+    }
+
+    let mut datagram = packet[l4..].to_vec();
+    let udp_len = datagram.len();
+    set_u16_at(&mut datagram, 4, udp_len as u16);
+    set_u16_at(&mut datagram, 6, 0);
+    let sum = pseudo_header_sum(packet, l3, 4, IPPROTO_UDP, udp_len);
+    let csum = match checksum::finish(checksum::sum(&datagram, sum)) {
+        0 => 0xffff,
+        csum => csum,
+    };
+    set_u16_at(&mut datagram, 6, csum);
+
+    let count = udp_len.div_ceil(size);
+    let mut fragments = Vec::with_capacity(count);
+    for (i, chunk) in datagram.chunks(size).enumerate() {
+        let mut fragment = Vec::with_capacity(l4 + chunk.len());
+        fragment.extend_from_slice(&packet[..l4]);
+        fragment.extend_from_slice(chunk);
+
+        let mut frag = ((i * size) / 8) as u16;
+        if i != count - 1 {
+            frag |= IPV4_FLAG_MF;
+        }
+        set_u16_at(&mut fragment, l3 + 6, frag);
+        let total_len = fragment.len() - l3;
+        set_ipv4_length(&mut fragment, l3, ihl, total_len);
+
+        fragments.push(fragment);
+    }
+    Ok(fragments)
+}
+
+#[cfg(test)]
+mod tests {
+    use driver::Mode;
+    use packet::checksum;
+    use super::*;
+
+    const FLAGS: u8 = TCP_FLAG_FIN | TCP_FLAG_PSH | 0x10;
+
+    fn tcp4(payload_len: usize) -> Vec<u8> {
+        let mut packet = vec![0u8; 40];
+        packet[0] = 0x45;
+        set_u16_at(&mut packet, 2, (40 + payload_len) as u16);
+        set_u16_at(&mut packet, 4, 0xfffe);
+        packet[8] = 64;
+        packet[9] = IPPROTO_TCP;
+        packet[12..20].clone_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+        set_u16_at(&mut packet, 20, 1234);
+        set_u16_at(&mut packet, 22, 80);
+        set_u32_at(&mut packet, 24, 0xffff_fc00);
+        packet[32] = 5 << 4;
+        packet[33] = FLAGS;
+        packet.extend((0..payload_len).map(|i| i as u8));
+        packet
+    }
+
+    fn tcp6(payload_len: usize) -> Vec<u8> {
+        let mut packet = vec![0u8; 60];
+        packet[0] = 0x60;
+        set_u16_at(&mut packet, 4, (20 + payload_len) as u16);
+        packet[6] = IPPROTO_TCP;
+        packet[7] = 64;
+        packet[23] = 1;
+        packet[39] = 2;
+        set_u32_at(&mut packet, 44, 7);
+        packet[52] = 5 << 4;
+        packet[53] = FLAGS;
+        packet.extend((0..payload_len).map(|i| i as u8));
+        packet
+    }
+
+    fn gso(gso_type: u8, gso_size: u16) -> VirtioNetHdr {
+        VirtioNetHdr { gso_type, gso_size, ..VirtioNetHdr::default() }
+    }
+
+    fn transport_valid(packet: &[u8], l3: usize, l4: usize, version: u8, protocol: u8) -> bool {
+        let sum = pseudo_header_sum(packet, l3, version, protocol, packet.len() - l4);
+        checksum::finish(checksum::sum(&packet[l4..], sum)) == 0
+    }
+
+    #[test]
+    fn segments_tcp4() {
+        let segments = segment(&gso(VIRTIO_NET_HDR_GSO_TCPV4, 1000), &tcp4(2500), Mode::Tun).unwrap();
+        assert_eq!(segments.iter().map(Vec::len).collect::<Vec<_>>(), vec![1040, 1040, 540]);
+
+        let mut payload = Vec::new();
+        for (i, segment) in segments.iter().enumerate() {
+            assert_eq!(u16_at(segment, 2) as usize, segment.len());
+            assert_eq!(u16_at(segment, 4), 0xfffeu16.wrapping_add(i as u16));
+            assert_eq!(checksum::checksum(&segment[..20]), 0);
+            assert!(transport_valid(segment, 0, 20, 4, IPPROTO_TCP));
+            assert_eq!(u32_at(segment, 24), 0xffff_fc00u32.wrapping_add(i as u32 * 1000));
+
+            let last = i == segments.len() - 1;
+            assert_eq!(segment[33] & (TCP_FLAG_FIN | TCP_FLAG_PSH) != 0, last);
+            assert_eq!(segment[33] & 0x10, 0x10);
+            payload.extend_from_slice(&segment[40..]);
+        }
+        assert_eq!(payload, &tcp4(2500)[40..]);
+    }
+
+    #[test]
+    fn segments_tcp6_in_tap_frame() {
+        let mut frame = vec![0u8; 14];
+        set_u16_at(&mut frame, 12, ETHERTYPE_IPV6);
+        frame.extend(tcp6(3000));
+
+        let segments = segment(&gso(VIRTIO_NET_HDR_GSO_TCPV6, 1400), &frame, Mode::Tap).unwrap();
+        assert_eq!(segments.len(), 3);
+        for (i, segment) in segments.iter().enumerate() {
+            assert_eq!(&segment[..14], &frame[..14]);
+            assert_eq!(u16_at(segment, 18) as usize, segment.len() - 14 - IPV6_HEADER_LEN);
+            assert_eq!(u32_at(segment, 58), 7 + i as u32 * 1400);
+            assert!(transport_valid(segment, 14, 54, 6, IPPROTO_TCP));
+        }
+    }
+
+    #[test]
+    fn fragments_udp() {
+        let mut packet = tcp4(0);
+        packet.truncate(20);
+        packet[9] = IPPROTO_UDP;
+        packet.extend_from_slice(&[0x30, 0x39, 0, 53, 0, 0, 0, 0]);
+        packet.extend(vec![0xab; 3000]);
+
+        let fragments = segment(&gso(VIRTIO_NET_HDR_GSO_UDP, 1485), &packet, Mode::Tun).unwrap();
+        assert_eq!(fragments.iter().map(|f| f.len() - 20).collect::<Vec<_>>(), vec![1480, 1480, 48]);
+        for (i, fragment) in fragments.iter().enumerate() {
+            let frag = u16_at(fragment, 6);
+            assert_eq!((frag & 0x1fff) as usize * 8, i * 1480);
+            assert_eq!(frag & IPV4_FLAG_MF != 0, i != 2);
+            assert_eq!(checksum::checksum(&fragment[..20]), 0);
+        }
+
+        let datagram = fragments.iter().flat_map(|f| f[20..].to_vec()).collect::<Vec<_>>();
+        assert_eq!(u16_at(&datagram, 4), 3008);
+        assert!(transport_valid(&[&fragments[0][..20], &datagram[..]].concat(), 0, 20, 4, IPPROTO_UDP));
+    }
+
+    #[test]
+    fn completes_partial_checksum() {
+        let mut packet = tcp4(33);
+        let sum = pseudo_header_sum(&packet, 0, 4, IPPROTO_TCP, 53);
+        set_u16_at(&mut packet, 36, !checksum::finish(sum));
+        let hdr = VirtioNetHdr { flags: VIRTIO_NET_HDR_F_NEEDS_CSUM, csum_start: 20, csum_offset: 16, ..VirtioNetHdr::default() };
+
+        let packets = segment(&hdr, &packet, Mode::Tun).unwrap();
+        assert_eq!(packets.len(), 1);
+        assert!(transport_valid(&packets[0], 0, 20, 4, IPPROTO_TCP));
+    }
+
+    #[test]
+    fn rejects_truncated_packets() {
+        let hdr = gso(VIRTIO_NET_HDR_GSO_TCPV4, 1000);
+        for len in 0..40 {
+            let err = segment(&hdr, &tcp4(0)[..len], Mode::Tun).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "length {}", len);
+        }
+        let err = segment(&gso(VIRTIO_NET_HDR_GSO_UDP, 1000), &[0x45, 0, 0, 0], Mode::Tun).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(segment(&hdr, &tcp4(0), Mode::Tun).is_err());
+    }
+}
@@ -5,11 +5,10 @@ use std::os::unix::io::AsRawFd;
 use mio::{Evented, Ready, Poll, PollOpt, Token};
 use mio::unix::EventedFd;
 
-use driver::Iface;
+use driver::{Device, Iface, Mode, PacketInfo, Result, VirtioNetHdr};
 
 pub struct MioWrapper {
     iface: Iface,
-    buffer_size: usize,
 }
 
 impl Evented for MioWrapper {
@@ -45,19 +44,82 @@ impl Write for MioWrapper {
     }
 }
 
-impl MioWrapper {
 
-    pub fn new(iface: Iface) -> io::Result<Self> {
-        let size = iface.get_mtu()?;
+impl MioWrapper {
 
+    pub fn new(iface: Iface) -> Result<Self> {
         iface.set_nonblocking()?;
         Ok(MioWrapper {
-            iface: iface,
-            buffer_size: size,
+            iface,
         })
     }
 
-    pub fn get_buffer_size(&self) -> usize {
-        self.buffer_size
+    pub fn set_mtu(&self, mtu: usize) -> Result<()> {
+        self.iface.set_mtu(mtu)
+    }
+
+    pub fn set_txqueuelen(&self, len: usize) -> Result<()> {
+        self.iface.set_txqueuelen(len)
+    }
+
+    pub fn get_ref(&self) -> &Iface {
+        &self.iface
+    }
+
+    pub fn get_mut(&mut self) -> &mut Iface {
+        &mut self.iface
+    }
+}
+
+// The packet information header, if any, is handled here: `read_packet`
+// strips it, `read_packet_info` returns it, and `write_packet` derives it
+// from the packet.
+impl Device for MioWrapper {
+    fn get_mode(&self) -> Mode {
+        self.iface.get_mode()
+    }
+
+    fn get_mtu(&self) -> io::Result<usize> {
+        Ok(self.iface.get_mtu()?)
+    }
+
+    fn get_buffer_size(&self) -> usize {
+        self.iface.get_buffer_size()
+    }
+
+    fn has_vnet_hdr(&self) -> bool {
+        self.iface.has_vnet_hdr()
+    }
+
+    fn read_packet(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_packet_info(buf).map(|(len, _)| len)
+    }
+
+    fn read_packet_info(&mut self, buf: &mut [u8]) -> io::Result<(usize, Option<PacketInfo>)> {
+        if !self.iface.has_packet_info() {
+            return self.iface.read(buf).map(|len| (len, None));
+        }
+
+        let (info, len) = self.iface.read_packet(buf)?;
+        if info.is_truncated() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("packet truncated to {} bytes", len)));
+        }
+        Ok((len, Some(info)))
+    }
+
+    fn write_packet(&mut self, packet: &[u8]) -> io::Result<usize> {
+        if !self.iface.has_packet_info() {
+            return self.iface.write(packet);
+        }
+
+        let payload = if self.iface.has_vnet_hdr() {
+            packet.get(VirtioNetHdr::LEN..).unwrap_or(&[])
+        } else {
+            packet
+        };
+        let info = PacketInfo::for_packet(self.iface.get_mode(), payload)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "unknown packet protocol"))?;
+        self.iface.write_packet(&info, packet)
     }
 }
@@ -0,0 +1,12 @@
+#[macro_use]
+extern crate bitflags;
+#[cfg(feature = "uring")]
+extern crate io_uring;
+extern crate libc;
+#[cfg(feature = "mio-wrapper")]
+extern crate mio;
+#[cfg(feature = "async-tokio")]
+extern crate tokio;
+
+pub mod driver;
+pub mod packet;
@@ -1,25 +1,57 @@
-#[macro_use]
-extern crate bitflags;
 extern crate libc;
+extern crate lsoup;
 extern crate mio;
 extern crate mio_extras;
 
-mod driver;
-
-use std::thread;
+use std::{env, io, mem, process, ptr, thread};
 use std::vec::Vec;
-use std::io::{Read, Write};
+use std::os::unix::io::RawFd;
+use std::sync::mpsc::TryRecvError;
 
 use mio::{Events, Token, Ready, PollOpt};
+use mio::unix::EventedFd;
 use mio_extras::channel::channel;
 
-use driver::{Iface, MioWrapper};
+use lsoup::driver::{complete_checksum, segment, BufferPool, Capabilities, Config, Device, DropPolicy, Error};
+use lsoup::driver::{Filter, Iface, MioWrapper, Mode, Netns, Offload, PacketBuf, PacketSocket, PcapDevice};
+use lsoup::driver::{PrivilegeDrop, Seccomp, TxQueue, VirtioNetHdr};
+use lsoup::packet::EthernetPacket;
+use lsoup::packet::icmp::echo_reply;
 
-fn main() {
-    let iface = Iface::new("tun0").unwrap();
-    iface.up();
+// Packets read per wakeup, idle buffers kept and packets waiting to be
+// written per queue.
+const BATCH: usize = 64;
+const POOL_SIZE: usize = 1024;
+const TX_QUEUE_SIZE: usize = 256;
+
+// SIGINT and SIGTERM are delivered through a signalfd so that the forwarding
+// loops can return and let `Iface` revert its configuration on drop.
+fn signal_fd() -> io::Result<RawFd> {
+    unsafe {
+        let mut mask: libc::sigset_t = mem::zeroed();
+        libc::sigemptyset(&mut mask);
+        libc::sigaddset(&mut mask, libc::SIGINT);
+        libc::sigaddset(&mut mask, libc::SIGTERM);
+
+        let rv = libc::pthread_sigmask(libc::SIG_BLOCK, &mask, ptr::null_mut());
+        if rv != 0 {
+            return Err(io::Error::from_raw_os_error(rv));
+        }
 
-    let mut stream = MioWrapper::new(iface).unwrap();
+        match libc::signalfd(-1, &mask, libc::SFD_CLOEXEC) {
+            -1 => Err(io::Error::last_os_error()),
+            fd => Ok(fd),
+        }
+    }
+}
+
+// Runs the forwarding loop of one queue until a signal arrives. Every queue
+// registers the same signalfd without reading it, so all of them wake up.
+// With `echo`, IPv4 echo requests on a TUN device go back as replies.
+fn forward<D: Device>(queue: usize, mut stream: D, signal: RawFd, policy: DropPolicy, echo: bool) {
+    let mode = stream.get_mode();
+    let vnet = stream.has_vnet_hdr();
+    let offset = if vnet { VirtioNetHdr::LEN } else { 0 };
 
     let (tx_1, rx_1) = channel();
     let (tx_2, rx_2) = channel();
@@ -36,11 +68,15 @@ fn main() {
 
             let size = poll.poll(&mut events, None).unwrap();
 
-            println!("subthread size={}", size);
+            println!("[{}] subthread size={}", queue, size);
             for event in &events {
                 if event.token() == Token(1) && event.readiness().is_readable() {
-                    let buf: Vec<u8> = rx.try_recv().unwrap();
-                    println!("recv from main, size={}", buf.len());
+                    let buf: PacketBuf = match rx.try_recv() {
+                        Ok(buf) => buf,
+                        Err(TryRecvError::Empty) => continue,
+                        Err(TryRecvError::Disconnected) => return,
+                    };
+                    println!("[{}] recv from main, size={}", queue, buf.len());
                     tx.send(buf).unwrap();
                 }
             }
@@ -52,27 +88,198 @@ fn main() {
     let poll = mio::Poll::new().unwrap();
     poll.register(&rx, Token(1), Ready::readable(), PollOpt::level()).unwrap();
     poll.register(&stream, Token(0), Ready::readable(), PollOpt::level()).unwrap();
+    poll.register(&EventedFd(&signal), Token(2), Ready::readable(), PollOpt::level()).unwrap();
 
     let mut events = Events::with_capacity(1024);
+    let pool = BufferPool::new(stream.get_buffer_size(), POOL_SIZE);
+    let mut batch = Vec::with_capacity(BATCH);
+    let mut tx_queue = TxQueue::new(TX_QUEUE_SIZE, policy);
+    let mut interest = Ready::readable();
 
     let mut i = 0;
-    loop {
+    'main: loop {
         let size = poll.poll(&mut events, None).unwrap();
 
-        println!("main size={}", size);
+        println!("[{}] main size={}", queue, size);
         for event in &events {
             i += 1;
             if event.token() == Token(0) && event.readiness().is_readable() {
-				let mut buf = vec![0u8; stream.get_buffer_size()];
-                let len = stream.read(&mut buf).unwrap();
-				buf.resize(len, 0);
-                println!("receive, {}, event={:?}", i, event);
-                tx.send(buf).unwrap();
-            } else if event.token() == Token(1) && event.readiness().is_readable() {
-                let buf: Vec<u8> = rx.try_recv().unwrap();
-                println!("receive from slave, size={}", buf.len());
-				stream.write(&buf).unwrap();
+                // A bad packet, such as one truncated to the buffer, costs
+                // only itself: those read before it are still forwarded.
+                if let Err(e) = stream.read_batch(&pool, BATCH, &mut batch) {
+                    println!("[{}] read failed: {}", queue, e);
+                }
+                println!("[{}] receive {} packet(s), {}, event={:?}", queue, batch.len(), i, event);
+                for mut buf in batch.drain(..) {
+                    if let Some(info) = buf.get_packet_info() {
+                        println!("  pi proto=0x{:04x} flags=0x{:04x}", info.proto, info.flags);
+                    }
+                    // Packets keep their vnet header on the way back, zeroed
+                    // once checksums are complete and GSO packets are split.
+                    if vnet {
+                        let hdr = VirtioNetHdr::parse(&buf).unwrap();
+                        println!("  vnet_hdr={:?}", hdr);
+                        if hdr.is_gso() {
+                            let packets = match segment(&hdr, &buf[VirtioNetHdr::LEN..], mode) {
+                                Ok(packets) => packets,
+                                Err(e) => {
+                                    println!("[{}] dropped GSO packet: {}", queue, e);
+                                    continue;
+                                },
+                            };
+                            for packet in packets {
+                                let mut out = pool.get();
+                                let len = VirtioNetHdr::LEN + packet.len();
+                                VirtioNetHdr::default().write(out.as_mut_buf());
+                                out.as_mut_buf()[VirtioNetHdr::LEN..len].clone_from_slice(&packet);
+                                out.set_len(len);
+                                tx.send(out).unwrap();
+                            }
+                            continue;
+                        }
+                        if let Err(e) = complete_checksum(&hdr, &mut buf[VirtioNetHdr::LEN..]) {
+                            println!("[{}] dropped packet: {}", queue, e);
+                            continue;
+                        }
+                        VirtioNetHdr::default().write(&mut buf);
+                    }
+                    if echo && mode == Mode::Tun && echo_reply(&mut buf[offset..]) {
+                        println!("  answered echo request");
+                    }
+                    if let (Mode::Tap, Some(frame)) = (mode, EthernetPacket::new(&buf[offset..])) {
+                        println!("  ethertype=0x{:04x} vlan={:?}", frame.payload_ethertype(), frame.vlan_id());
+                    }
+                    tx.send(buf).unwrap();
+                }
+            }
+            if event.token() == Token(1) && event.readiness().is_readable() {
+                let buf: PacketBuf = rx.try_recv().unwrap();
+                println!("[{}] receive from slave, size={}", queue, buf.len());
+                if !tx_queue.push(buf) {
+                    println!("[{}] tx queue full, dropped {}", queue, tx_queue.get_stats().dropped);
+                }
+            } else if event.token() == Token(2) {
+                break 'main;
+            }
+
+            // Written whenever something was queued or the device became
+            // writable; writable interest is only kept while packets wait.
+            if let Err(e) = tx_queue.flush(&mut stream) {
+                println!("[{}] write failed: {}", queue, e);
+            }
+            let wanted = if tx_queue.is_empty() {
+                Ready::readable()
+            } else {
+                Ready::readable() | Ready::writable()
+            };
+            if wanted != interest {
+                poll.reregister(&stream, Token(0), wanted, PollOpt::level()).unwrap();
+                interest = wanted;
             }
         }
     }
+    println!("[{}] tx {:?}, {} still queued", queue, tx_queue.get_stats(), tx_queue.len());
+}
+
+// The argument following `name`, as in `filter EXPR`.
+fn option<'a>(args: &'a [String], name: &str) -> Option<&'a String> {
+    args.iter().position(|arg| arg == name).and_then(|i| args.get(i + 1))
+}
+
+// usage: lsoup [tap] [vnet] [pi] [head-drop] [echo] [filter EXPR] [netns NAME] [user UID:GID] [sandbox] [QUEUES]
+//        lsoup replay INPUT OUTPUT [head-drop] [echo]
+//        lsoup attach IFACE [ring] [head-drop] [echo]
+fn main() {
+    let signal = signal_fd().unwrap();
+
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let policy = if args.iter().any(|arg| arg == "head-drop") {
+        DropPolicy::HeadDrop
+    } else {
+        DropPolicy::TailDrop
+    };
+    let echo = args.iter().any(|arg| arg == "echo");
+    if let (Some("replay"), Some(input), Some(output)) = (args.first().map(String::as_str), args.get(1), args.get(2)) {
+        let device = PcapDevice::new(input, output).unwrap();
+        forward(0, device, signal, policy, echo);
+        return;
+    }
+    if let (Some("attach"), Some(name)) = (args.first().map(String::as_str), args.get(1)) {
+        let device = if args.iter().any(|arg| arg == "ring") {
+            PacketSocket::with_ring(name, 256).unwrap()
+        } else {
+            PacketSocket::bind(name).unwrap()
+        };
+        forward(0, device, signal, policy, echo);
+        return;
+    }
+
+    let mode = if args.iter().any(|arg| arg == "tap") {
+        Mode::Tap
+    } else {
+        Mode::Tun
+    };
+    // Only TAP interfaces can be filtered by the kernel.
+    if mode == Mode::Tun && option(&args, "filter").is_some() {
+        eprintln!("usage: filter EXPR needs tap");
+        process::exit(2);
+    }
+    let name = match mode {
+        Mode::Tun => "tun%d",
+        Mode::Tap => "tap%d",
+    };
+    let vnet = args.iter().any(|arg| arg == "vnet");
+    let pi = args.iter().any(|arg| arg == "pi");
+    let queues = args.iter().filter_map(|arg| arg.parse::<usize>().ok()).next().unwrap_or(1);
+
+    let mut builder = Iface::builder()
+        .name(name)
+        .mode(mode)
+        .multi_queue(queues > 1)
+        .vnet_hdr(vnet)
+        .packet_info(pi);
+    // The interface and its routing live in the namespace, created unless it
+    // exists; the forwarding threads stay where they are.
+    if let Some(netns) = option(&args, "netns") {
+        let netns = match Netns::open(netns) {
+            Err(Error::Open(ref e)) if e.kind() == io::ErrorKind::NotFound => Netns::create(netns),
+            result => result,
+        }.unwrap();
+        builder = builder.netns(&netns);
+    }
+    let mut iface = builder.build().unwrap();
+    println!("{} with {} queue(s)", iface.get_name(), queues);
+    if vnet {
+        iface.set_offload(Offload::CSUM | Offload::TSO4 | Offload::TSO6 | Offload::TSO_ECN).unwrap();
+    }
+    if let Some(expression) = option(&args, "filter") {
+        iface.attach_filter(&Filter::compile(expression, mode).unwrap()).unwrap();
+    }
+    let mut ifaces = (1..queues).map(|_| iface.open_queue().unwrap()).collect::<Vec<_>>();
+    iface.up(&Config::default()).unwrap();
+    ifaces.insert(0, iface);
+    let streams = ifaces.into_iter().map(|iface| MioWrapper::new(iface).unwrap()).collect::<Vec<_>>();
+
+    // Setup is done, from here on only what `down` needs is kept. This has
+    // to happen before the workers start, capabilities are per thread.
+    if let Some(user) = option(&args, "user") {
+        let (uid, gid) = user.split_once(':')
+            .and_then(|(uid, gid)| Some((uid.parse().ok()?, gid.parse().ok()?)))
+            .expect("user must be UID:GID");
+        let keep = Capabilities::NET_ADMIN;
+        PrivilegeDrop::new(uid, gid).keep(keep).apply().unwrap();
+        println!("running as {}:{} with {:?}", uid, gid, keep);
+    }
+    // After the drop, as setresuid and capset are not on the allowlist.
+    if args.iter().any(|arg| arg == "sandbox") {
+        Seccomp::new().apply().unwrap();
+    }
+
+    let workers = streams.into_iter().enumerate().map(|(queue, stream)| {
+        thread::spawn(move || forward(queue, stream, signal, policy, echo))
+    }).collect::<Vec<_>>();
+
+    for worker in workers {
+        worker.join().unwrap();
+    }
 }
@@ -0,0 +1,37 @@
+/*
+ * Reference:
+ *  https://tools.ietf.org/html/rfc1071
+ *  https://github.com/libpnet/libpnet/blob/master/pnet_packet/src/util.rs
+ *
+ * Date: Oct 18 CST 2026
+ */
+
+// Adds `data` as big-endian 16-bit words to the running one's complement sum
+// `initial`, padding an odd trailing byte with zero.
+pub fn sum(data: &[u8], initial: u32) -> u32 {
+    let mut sum = initial as u64;
+    let mut chunks = data.chunks_exact(2);
+    for word in &mut chunks {
+        sum += u16::from_be_bytes([word[0], word[1]]) as u64;
+    }
+    if let [byte] = chunks.remainder() {
+        sum += (*byte as u64) << 8;
+    }
+
+    while sum >> 16 != 0 {
+        sum = (sum >> 16) + (sum & 0xffff);
+    }
+    sum as u32
+}
+
+pub fn finish(sum: u32) -> u16 {
+    let mut sum = sum;
+    while sum >> 16 != 0 {
+        sum = (sum >> 16) + (sum & 0xffff);
+    }
+    !sum as u16
+}
+
+pub fn checksum(data: &[u8]) -> u16 {
+    finish(sum(data, 0))
+}
@@ -0,0 +1,187 @@
+/*
+ * Reference:
+ *  https://github.com/faern/rips/blob/master/packets/src/ethernet.rs
+ *  https://en.wikipedia.org/wiki/IEEE_802.1Q
+ *
+ * Date: Oct 18 CST 2026
+ */
+
+/*
+    Ethernet II frame, as read from a TAP device (no preamble, no FCS):
+         0                     6                     12          14
+        +---------------------+---------------------+-----------+---------+
+        |     Destination     |       Source        | EtherType | Payload |
+        +---------------------+---------------------+-----------+---------+
+
+    802.1Q tagged frame, the tag sits between the source address and the EtherType:
+         12          14                    16          18
+        +-----------+---------------------+-----------+---------+
+        |  0x8100   | PCP | DEI |   VID   | EtherType | Payload |
+        +-----------+---------------------+-----------+---------+
+*/
+
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+pub const ETHERTYPE_ARP: u16 = 0x0806;
+pub const ETHERTYPE_VLAN: u16 = 0x8100;
+pub const ETHERTYPE_IPV6: u16 = 0x86dd;
+
+const VLAN_TAG_LEN: usize = 4;
+
+fn mac(bytes: &[u8]) -> [u8; 6] {
+    let mut mac = [0u8; 6];
+    mac.clone_from_slice(&bytes[..6]);
+    mac
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct EthernetPacket<'a>(&'a [u8]);
+pub struct MutEthernetPacket<'a>(&'a mut [u8]);
+
+impl<'a> EthernetPacket<'a> {
+    pub const MIN_LEN: usize = 14;
+
+    pub fn new(data: &'a [u8]) -> Option<EthernetPacket<'a>> {
+        if data.len() >= Self::MIN_LEN {
+            Some(EthernetPacket(data))
+        } else {
+            None
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        self.0
+    }
+
+    pub fn destination(&self) -> [u8; 6] {
+        mac(&self.0[0..6])
+    }
+
+    pub fn source(&self) -> [u8; 6] {
+        mac(&self.0[6..12])
+    }
+
+    pub fn ethertype(&self) -> u16 {
+        u16::from_be_bytes([self.0[12], self.0[13]])
+    }
+
+    pub fn vlan_tci(&self) -> Option<u16> {
+        if self.ethertype() == ETHERTYPE_VLAN && self.0.len() >= Self::MIN_LEN + VLAN_TAG_LEN {
+            Some(u16::from_be_bytes([self.0[14], self.0[15]]))
+        } else {
+            None
+        }
+    }
+
+    pub fn vlan_id(&self) -> Option<u16> {
+        self.vlan_tci().map(|tci| tci & 0x0fff)
+    }
+
+    pub fn payload_ethertype(&self) -> u16 {
+        match self.vlan_tci() {
+            Some(_) => u16::from_be_bytes([self.0[16], self.0[17]]),
+            None => self.ethertype(),
+        }
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        match self.vlan_tci() {
+            Some(_) => &self.0[Self::MIN_LEN + VLAN_TAG_LEN..],
+            None => &self.0[Self::MIN_LEN..],
+        }
+    }
+}
+
+impl<'a> MutEthernetPacket<'a> {
+
+    pub fn new(data: &'a mut [u8]) -> Option<MutEthernetPacket<'a>> {
+        if data.len() >= EthernetPacket::MIN_LEN {
+            Some(MutEthernetPacket(data))
+        } else {
+            None
+        }
+    }
+
+    pub fn as_immutable(&self) -> EthernetPacket<'_> {
+        EthernetPacket(&self.0[..])
+    }
+
+    pub fn data(&mut self) -> &mut [u8] {
+        self.0
+    }
+
+    pub fn set_destination(&mut self, destination: [u8; 6]) {
+        self.0[0..6].clone_from_slice(&destination);
+    }
+
+    pub fn set_source(&mut self, source: [u8; 6]) {
+        self.0[6..12].clone_from_slice(&source);
+    }
+
+    pub fn set_ethertype(&mut self, ethertype: u16) {
+        self.0[12..14].clone_from_slice(&ethertype.to_be_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EthernetPacket, MutEthernetPacket, ETHERTYPE_IPV4, ETHERTYPE_IPV6, ETHERTYPE_VLAN};
+
+    const DESTINATION: [u8; 6] = [0x02, 0, 0, 0, 0, 1];
+    const SOURCE: [u8; 6] = [0x02, 0, 0, 0, 0, 2];
+
+    fn frame(ethertype: u16, rest: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::new();
+        frame.extend_from_slice(&DESTINATION);
+        frame.extend_from_slice(&SOURCE);
+        frame.extend_from_slice(&ethertype.to_be_bytes());
+        frame.extend_from_slice(rest);
+        frame
+    }
+
+    #[test]
+    fn reads_untagged_frames() {
+        let frame = frame(ETHERTYPE_IPV4, &[0x45, 0]);
+        let packet = EthernetPacket::new(&frame).unwrap();
+        assert_eq!(packet.destination(), DESTINATION);
+        assert_eq!(packet.source(), SOURCE);
+        assert_eq!(packet.ethertype(), ETHERTYPE_IPV4);
+        assert_eq!(packet.vlan_id(), None);
+        assert_eq!(packet.payload_ethertype(), ETHERTYPE_IPV4);
+        assert_eq!(packet.payload(), &[0x45, 0]);
+        assert!(EthernetPacket::new(&frame[..13]).is_none());
+    }
+
+    #[test]
+    fn reads_tagged_frames() {
+        // PCP 5, VID 100.
+        let frame = frame(ETHERTYPE_VLAN, &[0xa0, 100, 0x86, 0xdd, 0x60]);
+        let packet = EthernetPacket::new(&frame).unwrap();
+        assert_eq!(packet.ethertype(), ETHERTYPE_VLAN);
+        assert_eq!(packet.vlan_tci(), Some(0xa064));
+        assert_eq!(packet.vlan_id(), Some(100));
+        assert_eq!(packet.payload_ethertype(), ETHERTYPE_IPV6);
+        assert_eq!(packet.payload(), &[0x60]);
+    }
+
+    #[test]
+    fn ignores_truncated_tags() {
+        let frame = frame(ETHERTYPE_VLAN, &[0, 100, 0x08]);
+        let packet = EthernetPacket::new(&frame).unwrap();
+        assert_eq!(packet.vlan_tci(), None);
+        assert_eq!(packet.payload_ethertype(), ETHERTYPE_VLAN);
+        assert_eq!(packet.payload(), &[0, 100, 0x08]);
+    }
+
+    // Frames behind a vnet header or PI prefix start at any offset.
+    #[test]
+    fn reads_unaligned_frames() {
+        let mut buf = vec![0u8];
+        buf.extend_from_slice(&frame(ETHERTYPE_VLAN, &[0, 7, 0x08, 0x00]));
+        assert_eq!(EthernetPacket::new(&buf[1..]).unwrap().payload_ethertype(), ETHERTYPE_IPV4);
+
+        let mut packet = MutEthernetPacket::new(&mut buf[1..]).unwrap();
+        packet.set_ethertype(ETHERTYPE_IPV6);
+        assert_eq!(packet.as_immutable().ethertype(), ETHERTYPE_IPV6);
+        assert_eq!(&buf[13..15], &[0x86, 0xdd]);
+    }
+}
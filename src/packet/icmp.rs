@@ -0,0 +1,58 @@
+/*
+ * Reference:
+ *  https://tools.ietf.org/html/rfc792
+ *  tun-tap/icmp-echo
+ *
+ * Date: Oct 18 CST 2026
+ */
+
+use packet::checksum::checksum;
+
+pub const ICMP_ECHO_REPLY: u8 = 0;
+pub const ICMP_ECHO_REQUEST: u8 = 8;
+
+const IPPROTO_ICMP: u8 = 1;
+
+/*
+    ICMP echo request and reply, behind the IPv4 header:
+         0         7 8        15 16                  31
+        +-----------+-----------+----------------------+
+        |   Type    |   Code    |       Checksum       |
+        +-----------+-----------+----------------------+
+        |      Identifier       |   Sequence Number    |
+        +-----------------------+----------------------+
+        |                     Data                     |
+        +----------------------------------------------+
+*/
+
+// Turns the IPv4 ICMP echo request in `packet` into its reply in place, the
+// way icmp-echo answers pings on its TUN interface: addresses swapped, type
+// changed and both checksums recomputed, identifier, sequence number and data
+// kept. Returns false, leaving `packet` untouched, for anything else.
+pub fn echo_reply(packet: &mut [u8]) -> bool {
+    if packet.len() < 20 || packet[0] >> 4 != 4 || packet[9] != IPPROTO_ICMP {
+        return false;
+    }
+    let ihl = ((packet[0] & 0x0f) as usize) * 4;
+    let total_len = u16::from_be_bytes([packet[2], packet[3]]) as usize;
+    if ihl < 20 || total_len < ihl + 8 || total_len > packet.len() {
+        return false;
+    }
+    let (header, icmp) = packet[..total_len].split_at_mut(ihl);
+    if icmp[0] != ICMP_ECHO_REQUEST || icmp[1] != 0 {
+        return false;
+    }
+
+    let source = [header[12], header[13], header[14], header[15]];
+    header.copy_within(16..20, 12);
+    header[16..20].clone_from_slice(&source);
+    header[10..12].clone_from_slice(&[0, 0]);
+    let ip_checksum = checksum(header);
+    header[10..12].clone_from_slice(&ip_checksum.to_be_bytes());
+
+    icmp[0] = ICMP_ECHO_REPLY;
+    icmp[2..4].clone_from_slice(&[0, 0]);
+    let icmp_checksum = checksum(icmp);
+    icmp[2..4].clone_from_slice(&icmp_checksum.to_be_bytes());
+    true
+}
@@ -0,0 +1,8 @@
+pub mod checksum;
+mod ethernet;
+pub mod icmp;
+
+pub use packet::ethernet::{
+    EthernetPacket, MutEthernetPacket,
+    ETHERTYPE_ARP, ETHERTYPE_IPV4, ETHERTYPE_IPV6, ETHERTYPE_VLAN
+};
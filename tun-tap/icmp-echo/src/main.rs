@@ -5,6 +5,7 @@
  */
 
 extern crate libc;
+extern crate lsoup;
 
 mod types;
 #[macro_use]
@@ -29,8 +30,10 @@ fn cleanup(signum: i32) {
     if signum == libc::SIGINT {
         unsafe {
             match TUN {
-                Some(ref tun) =>
-                    tun.down(),
+                Some(ref mut tun) =>
+                    if let Err(e) = tun.down() {
+                        eprintln!("tun down: {}", e);
+                    },
                 None => {},
             }
         }
@@ -117,13 +120,22 @@ fn icmp_echo(ori_ipv4_packet: IPv4Packet, total_len: usize) -> Option<Vec<u8>> {
 
 fn main() {
 
-    match Tun::create(TUN_NAME) {
-        Ok(result) => unsafe {
-            result.up();
-            TUN = Some(result);
+    // `up` has reverted whatever it applied when it fails, so that there is
+    // nothing left for `cleanup` to tear down.
+    let mut tun = match Tun::create(TUN_NAME) {
+        Ok(tun) => tun,
+        Err(e) => {
+            eprintln!("tun create: {}", e);
+            process::exit(1);
         },
-        Err(e) => panic!(e),
     };
+    if let Err(e) = tun.up() {
+        eprintln!("tun up: {}", e);
+        process::exit(1);
+    }
+    unsafe {
+        TUN = Some(tun);
+    }
 
     unsafe {
         libc::signal(libc::SIGINT, cleanup as usize);